@@ -0,0 +1,154 @@
+use crate::{
+    config::{AlertCondition, AlertRule, AlertsConfig},
+    res::query_res,
+    webhook::send_webhook,
+};
+use screeps_rust_api::ScreepsApi;
+use serde_json::json;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+
+impl AlertRule {
+    /// 规则名称，未配置时根据规则内容生成
+    pub fn display_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            let condition = match self.condition {
+                AlertCondition::Below => "below",
+                AlertCondition::Above => "above",
+            };
+            format!(
+                "{} {} {} {} {}",
+                self.username, self.shard, self.resource, condition, self.threshold
+            )
+        })
+    }
+
+    /// 根据资源数量判断是否触发告警
+    pub fn is_triggered(&self, value: i64) -> bool {
+        match self.condition {
+            AlertCondition::Below => value < self.threshold,
+            AlertCondition::Above => value > self.threshold,
+        }
+    }
+
+    /// 从查询结果中读取规则关注的资源数量，`all` 时为所有 shard 之和
+    pub fn value(&self, res: &HashMap<String, HashMap<String, i32>>) -> i64 {
+        res.iter()
+            .filter(|(shard, _)| self.shard == "all" || **shard == self.shard)
+            .map(|(_, res)| *res.get(&self.resource).unwrap_or(&0) as i64)
+            .sum()
+    }
+}
+
+/// 启动后台告警任务，没有配置规则时不启动
+pub fn spawn_alert_job(
+    api: Arc<ScreepsApi>,
+    config: Arc<crate::config::Config>,
+) -> Option<JoinHandle<()>> {
+    if config.alerts.rules.is_empty() {
+        return None;
+    }
+    Some(tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        // 每条规则当前是否处于告警状态，只在状态变化时发送通知
+        let mut firing: HashMap<String, bool> = HashMap::new();
+        let mut interval = tokio::time::interval(Duration::from_secs(config.alerts.interval));
+        loop {
+            interval.tick().await;
+            check_alerts(&api, &config.alerts, &client, &mut firing).await;
+        }
+    }))
+}
+
+/// 检查所有告警规则，每个玩家只查询一次资源
+async fn check_alerts(
+    api: &ScreepsApi,
+    config: &AlertsConfig,
+    client: &reqwest::Client,
+    firing: &mut HashMap<String, bool>,
+) {
+    let mut results = HashMap::new();
+    for rule in &config.rules {
+        if !results.contains_key(&rule.username) {
+            let res = query_res(api, &rule.username, "all").await;
+            if let Err(e) = &res {
+                eprintln!("Failed to query res for alert of {}: {}", rule.username, e);
+            }
+            results.insert(rule.username.clone(), res.ok());
+        }
+        let Some(res) = &results[&rule.username] else {
+            continue;
+        };
+
+        let name = rule.display_name();
+        let value = rule.value(res);
+        let triggered = rule.is_triggered(value);
+        let was_triggered = firing.get(&name).copied().unwrap_or(false);
+        if triggered == was_triggered {
+            continue;
+        }
+
+        let (event, message) = if triggered {
+            ("alert", format!("[告警] {}：当前数量 {}", name, value))
+        } else {
+            ("recovery", format!("[恢复] {}：当前数量 {}", name, value))
+        };
+        let data = json!({
+            "event": event,
+            "rule": name,
+            "username": rule.username,
+            "shard": rule.shard,
+            "resource": rule.resource,
+            "value": value,
+            "threshold": rule.threshold,
+        });
+        // 至少一个 webhook 发送成功后才更新告警状态，全部失败时下次检查重新发送
+        let mut sent = config.webhooks.is_empty();
+        for webhook in &config.webhooks {
+            match send_webhook(client, webhook, &message, data.clone()).await {
+                Ok(_) => sent = true,
+                Err(e) => eprintln!("Failed to send alert to {}: {}", webhook.url, e),
+            }
+        }
+        if sent {
+            firing.insert(name, triggered);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alert_rule() {
+        let rule = AlertRule {
+            name: None,
+            username: "player".to_string(),
+            shard: "shard3".to_string(),
+            resource: "energy".to_string(),
+            condition: AlertCondition::Below,
+            threshold: 500_000,
+        };
+        let res = HashMap::from([
+            (
+                "shard3".to_string(),
+                HashMap::from([("energy".to_string(), 400_000)]),
+            ),
+            (
+                "shard2".to_string(),
+                HashMap::from([("energy".to_string(), 200_000)]),
+            ),
+        ]);
+        assert_eq!(rule.display_name(), "player shard3 energy below 500000");
+        assert_eq!(rule.value(&res), 400_000);
+        assert!(rule.is_triggered(rule.value(&res)));
+
+        let rule = AlertRule {
+            shard: "all".to_string(),
+            ..rule
+        };
+        assert_eq!(rule.value(&res), 600_000);
+        assert!(!rule.is_triggered(rule.value(&res)));
+    }
+}
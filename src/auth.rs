@@ -0,0 +1,172 @@
+use crate::{ApiResponse, config::ApiKeyConfig, config::Config, privacy, v1};
+use axum::{
+    extract::{Query, Request, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// 不需要认证的接口
+/// 玩家设置和 token 注册接口使用玩家自己的 Screeps token 验证身份
+const PUBLIC_PATHS: [&str; 9] = [
+    "/",
+    "/healthz",
+    "/readyz",
+    "/version",
+    "/openapi.json",
+    "/privacy",
+    "/api/v1/privacy",
+    "/tokens",
+    "/api/v1/tokens",
+];
+
+/// 请求使用的 API key，认证通过后放入请求的 extensions
+pub type ApiKey = Arc<ApiKeyConfig>;
+
+impl ApiKeyConfig {
+    /// 是否允许查询指定玩家和 shard，玩家名称不区分大小写，shard 为 `all` 时要求不限制 shard
+    pub fn allows(&self, username: &str, shard: &str) -> bool {
+        let name = privacy::normalize(username);
+        let user_allowed =
+            self.users.is_empty() || self.users.iter().any(|u| privacy::normalize(u) == name);
+        let shard_allowed = self.shards.is_empty() || self.shards.iter().any(|s| s == shard);
+        user_allowed && shard_allowed
+    }
+}
+
+/// 是否为不需要认证的接口
+pub fn is_public(path: &str) -> bool {
+    PUBLIC_PATHS.contains(&path) || path.starts_with("/docs")
+}
+
+/// 从请求中读取 API key，支持 `Authorization: Bearer <key>`、`X-API-Key` 请求头和 `api_key` 查询参数
+/// 浏览器中的 WebSocket 和图片无法设置请求头，可以使用查询参数
+fn request_key<'a>(headers: &'a HeaderMap, query: &'a HashMap<String, String>) -> Option<&'a str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| {
+            headers
+                .get("x-api-key")
+                .and_then(|value| value.to_str().ok())
+        })
+        .or_else(|| query.get("api_key").map(String::as_str))
+}
+
+/// 比较两个 key 是否相同，耗时与相同的前缀长度无关，避免通过响应时间猜测 key
+pub fn key_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// 拒绝请求的响应，`/api/v1` 下的接口使用 v1 的响应格式
+pub fn reject(path: &str, status: StatusCode, code: &'static str, message: &str) -> Response {
+    if path.starts_with("/api/v1") {
+        return v1::error(status, code, message.to_string());
+    }
+    (
+        status,
+        Json(ApiResponse::<()> {
+            success: false,
+            data: None,
+            error: Some(message.to_string()),
+        }),
+    )
+        .into_response()
+}
+
+/// 认证中间件，未开启认证时直接放行
+/// 开启后除公开接口外都需要有效的 API key，并检查查询参数中的 `username` 和 `shard` 是否在 key 允许的范围内
+pub async fn auth_middleware(
+    State(config): State<Arc<Config>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    if !config.auth.enabled || is_public(&path) {
+        return next.run(request).await;
+    }
+
+    let query: HashMap<String, String> = Query::try_from_uri(request.uri())
+        .map(|Query(query)| query)
+        .unwrap_or_default();
+    let Some(key) = request_key(request.headers(), &query)
+        .and_then(|key| config.auth.keys.iter().find(|k| key_eq(&k.key, key)))
+    else {
+        return reject(
+            &path,
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            "API key 无效",
+        );
+    };
+
+    if let Some(username) = query.get("username") {
+        let shard = query.get("shard").map(String::as_str).unwrap_or("all");
+        if !key.allows(username, shard) {
+            return reject(
+                &path,
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                "API key 无权查询该玩家或 shard",
+            );
+        }
+    }
+
+    request
+        .extensions_mut()
+        .insert::<ApiKey>(Arc::new(key.clone()));
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_key_allows() {
+        let key = ApiKeyConfig {
+            name: "test".to_string(),
+            key: "secret".to_string(),
+            users: vec!["player".to_string()],
+            shards: vec!["shard3".to_string()],
+            quota: None,
+        };
+        assert!(key.allows("player", "shard3"));
+        assert!(key.allows("Player", "shard3"));
+        assert!(!key.allows("player", "shard2"));
+        assert!(!key.allows("player", "all"));
+        assert!(!key.allows("other", "shard3"));
+
+        let key = ApiKeyConfig {
+            users: Vec::new(),
+            shards: Vec::new(),
+            ..key
+        };
+        assert!(key.allows("other", "all"));
+    }
+
+    #[test]
+    fn test_request_key() {
+        let mut headers = HeaderMap::new();
+        let query = HashMap::from([("api_key".to_string(), "from_query".to_string())]);
+        assert_eq!(request_key(&headers, &query), Some("from_query"));
+        headers.insert("x-api-key", "from_header".parse().unwrap());
+        assert_eq!(request_key(&headers, &query), Some("from_header"));
+        headers.insert(header::AUTHORIZATION, "Bearer from_bearer".parse().unwrap());
+        assert_eq!(request_key(&headers, &query), Some("from_bearer"));
+    }
+
+    #[test]
+    fn test_key_eq() {
+        assert!(key_eq("secret", "secret"));
+        assert!(!key_eq("secret", "secreT"));
+        assert!(!key_eq("secret", "secret2"));
+        assert!(!key_eq("", "secret"));
+    }
+}
@@ -0,0 +1,358 @@
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+
+/// 服务配置，从 `CONFIG_PATH` 指定的 JSON 文件读取，默认为 `config.json`
+/// 文件不存在时使用默认配置
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    pub balance: BalanceConfig,
+    pub alerts: AlertsConfig,
+    pub reports: Vec<ReportConfig>,
+    pub ingest: IngestConfig,
+    pub cpu: CollectorConfig,
+    pub stats: CollectorConfig,
+    pub auth: AuthConfig,
+    pub rate_limit: RateLimitConfig,
+    pub privacy: PrivacyConfig,
+    pub http: HttpConfig,
+}
+
+/// 房间资源平衡配置
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct BalanceConfig {
+    /// 每个房间每种资源的目标数量
+    pub targets: HashMap<String, i32>,
+    /// 单次传送的最小数量，低于该值的传送会被忽略
+    pub min_transfer: i32,
+}
+
+impl Default for BalanceConfig {
+    fn default() -> Self {
+        BalanceConfig {
+            targets: HashMap::from([("energy".to_string(), 100_000)]),
+            min_transfer: 1000,
+        }
+    }
+}
+
+/// 资源告警配置
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct AlertsConfig {
+    /// 检查间隔（秒）
+    pub interval: u64,
+    pub rules: Vec<AlertRule>,
+    /// 告警发送到的 webhook
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        AlertsConfig {
+            interval: 300,
+            rules: Vec::new(),
+            webhooks: Vec::new(),
+        }
+    }
+}
+
+/// 告警条件
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertCondition {
+    /// 资源数量低于阈值
+    Below,
+    /// 资源数量高于阈值
+    Above,
+}
+
+/// 告警规则，如 "shard3 的 energy 低于 500k"
+#[derive(Deserialize, Clone)]
+pub struct AlertRule {
+    /// 规则名称，默认根据规则内容生成
+    pub name: Option<String>,
+    pub username: String,
+    /// 目标 shard，`all` 表示所有 shard 之和
+    #[serde(default = "default_shard")]
+    pub shard: String,
+    pub resource: String,
+    pub condition: AlertCondition,
+    pub threshold: i64,
+}
+
+/// webhook 类型，决定消息体格式
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookKind {
+    /// 通用 JSON 消息
+    #[default]
+    Generic,
+    /// Discord 格式，消息放在 `content` 字段
+    Discord,
+    /// Slack 格式，消息放在 `text` 字段
+    Slack,
+}
+
+/// webhook 配置
+#[derive(Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub kind: WebhookKind,
+}
+
+/// 定时报告的图片类型
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportKind {
+    /// 资源图片
+    #[default]
+    Res,
+    /// 玩家概览卡片
+    Overview,
+    /// 防御状态图片
+    Defense,
+}
+
+/// 定时报告配置
+#[derive(Deserialize, Clone)]
+pub struct ReportConfig {
+    pub name: String,
+    /// cron 表达式，格式为 `分 时 日 月 周`，使用服务器本地时间
+    pub cron: String,
+    pub username: String,
+    #[serde(default = "default_shard")]
+    pub shard: String,
+    #[serde(default)]
+    pub kind: ReportKind,
+    pub webhook: WebhookConfig,
+    /// 上传失败后的重试次数
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+/// Screeps 服务器 socket 数据接入配置
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct IngestConfig {
+    pub enabled: bool,
+    /// socket 地址
+    pub url: String,
+    /// 认证 token，未配置时读取环境变量 `SCREEPS_TOKEN`
+    pub token: Option<String>,
+    /// 需要接入的玩家，订阅这些玩家所有房间的房间对象、CPU 和控制台
+    pub usernames: Vec<String>,
+    /// 房间数据超过该时间（秒）没有更新时改为 HTTP 查询
+    pub max_age: u64,
+}
+
+impl Default for IngestConfig {
+    fn default() -> Self {
+        IngestConfig {
+            enabled: false,
+            url: "wss://screeps.com/socket/websocket".to_string(),
+            token: None,
+            usernames: Vec::new(),
+            max_age: 60,
+        }
+    }
+}
+
+/// 统计数据采集配置，用于 CPU 和内存采集以及自定义统计数据采集
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct CollectorConfig {
+    /// 采集间隔（秒）
+    pub interval: u64,
+    pub targets: Vec<StatsTarget>,
+}
+
+impl Default for CollectorConfig {
+    fn default() -> Self {
+        CollectorConfig {
+            interval: 60,
+            targets: Vec::new(),
+        }
+    }
+}
+
+/// 统计数据采集目标，读取玩家指定 shard 的 Memory 或内存段
+#[derive(Deserialize, Clone)]
+pub struct StatsTarget {
+    pub username: String,
+    pub shard: String,
+    /// Memory 中统计数据的路径
+    #[serde(default = "default_stats_path")]
+    pub path: String,
+    /// 内存段编号，配置后读取该内存段而不是 Memory 路径
+    pub segment: Option<u32>,
+}
+
+fn default_stats_path() -> String {
+    "stats".to_string()
+}
+
+/// 接口认证配置
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// 开启后访问查询接口需要提供 API key
+    pub enabled: bool,
+    pub keys: Vec<ApiKeyConfig>,
+}
+
+/// API key 配置
+#[derive(Deserialize, Clone)]
+pub struct ApiKeyConfig {
+    pub name: String,
+    pub key: String,
+    /// 允许查询的玩家，为空表示所有玩家
+    #[serde(default)]
+    pub users: Vec<String>,
+    /// 允许查询的 shard，为空表示所有 shard
+    #[serde(default)]
+    pub shards: Vec<String>,
+    /// 该 key 的限流额度，未配置时使用 `rate_limit` 中的默认额度
+    #[serde(default)]
+    pub quota: Option<QuotaConfig>,
+}
+
+/// HTTP 响应配置
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// 允许跨域访问的来源，如 `https://dashboard.example.com`，`*` 表示允许所有来源，为空时不允许跨域
+    pub cors_origins: Vec<String>,
+    /// 是否按 `Accept-Encoding` 使用 gzip 或 brotli 压缩响应，图片和事件流不压缩
+    pub compression: bool,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            cors_origins: Vec::new(),
+            compression: true,
+        }
+    }
+}
+
+/// 玩家名单配置，名称不区分大小写
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    /// 只提供这些玩家的数据，为空表示不限制
+    pub allow: Vec<String>,
+    /// 不提供这些玩家的数据
+    pub deny: Vec<String>,
+}
+
+/// 限流配置
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// 默认额度，用于按 IP 限流的客户端和未单独配置额度的 API key
+    #[serde(flatten)]
+    pub quota: QuotaConfig,
+}
+
+/// 令牌桶额度
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct QuotaConfig {
+    /// 令牌桶容量
+    pub capacity: f64,
+    /// 每秒补充的令牌数
+    pub refill: f64,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        QuotaConfig {
+            capacity: 200.0,
+            refill: 2.0,
+        }
+    }
+}
+
+impl QuotaConfig {
+    /// 检查额度，容量和每秒补充的令牌数都必须大于 0
+    fn validate(&self, name: &str) -> Result<(), String> {
+        if self.capacity <= 0.0 {
+            return Err(format!("{}.capacity 必须大于 0", name));
+        }
+        if self.refill <= 0.0 {
+            return Err(format!("{}.refill 必须大于 0", name));
+        }
+        Ok(())
+    }
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+fn default_shard() -> String {
+    "all".to_string()
+}
+
+impl Config {
+    /// 检查配置是否有效，返回第一个错误
+    pub fn validate(&self) -> Result<(), String> {
+        if self.alerts.interval == 0 {
+            return Err("alerts.interval 必须大于 0".to_string());
+        }
+        if self.cpu.interval == 0 {
+            return Err("cpu.interval 必须大于 0".to_string());
+        }
+        if self.stats.interval == 0 {
+            return Err("stats.interval 必须大于 0".to_string());
+        }
+        self.rate_limit.quota.validate("rate_limit")?;
+        for key in &self.auth.keys {
+            if let Some(quota) = &key.quota {
+                quota.validate(&format!("auth.keys[{}].quota", key.name))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 读取配置文件
+pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
+    let path = std::env::var("CONFIG_PATH").unwrap_or("config.json".to_string());
+    if !Path::new(&path).exists() {
+        return Ok(Config::default());
+    }
+    let content = std::fs::read_to_string(&path)?;
+    let config: Config = serde_json::from_str(&content)?;
+    config.validate()?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(Config::default().validate().is_ok());
+        let config: Config = serde_json::from_str(r#"{"alerts": {"interval": 0}}"#).unwrap();
+        assert!(config.validate().is_err());
+        let config: Config = serde_json::from_str(r#"{"cpu": {"interval": 0}}"#).unwrap();
+        assert!(config.validate().is_err());
+        let config: Config = serde_json::from_str(r#"{"stats": {"interval": 0}}"#).unwrap();
+        assert!(config.validate().is_err());
+        let config: Config = serde_json::from_str(r#"{"rate_limit": {"refill": 0}}"#).unwrap();
+        assert!(config.validate().is_err());
+        let config: Config = serde_json::from_str(r#"{"rate_limit": {"capacity": -1}}"#).unwrap();
+        assert!(config.validate().is_err());
+        let config: Config = serde_json::from_str(
+            r#"{"auth": {"keys": [{"name": "a", "key": "k", "quota": {"capacity": 0}}]}}"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_err());
+    }
+}
@@ -78,6 +78,70 @@ pub const B_GREEN_RES: [&str; 7] = ["LH", "LH2O", "XLH2O", "LO", "LHO2", "XLHO2"
 /// 白色基础资源
 pub const B_WHITE_RES: [&str; 7] = ["GH", "GH2O", "XGH2O", "GO", "GHO2", "XGHO2", "ghodium"];
 
+/// GCL 等级计算指数
+pub const GCL_POW: f64 = 2.4;
+
+/// GCL 等级计算倍数
+pub const GCL_MULTIPLY: f64 = 1_000_000.0;
+
+/// GPL 等级计算指数
+pub const POWER_LEVEL_POW: f64 = 2.0;
+
+/// GPL 等级计算倍数
+pub const POWER_LEVEL_MULTIPLY: f64 = 1000.0;
+
+/// 控制器升到下一级所需进度，下标为当前等级
+pub const CONTROLLER_LEVELS: [u64; 8] = [0, 200, 45000, 135000, 405000, 1215000, 3645000, 10935000];
+
+/// 降级计时低于该值时无法开启安全模式
+pub const CONTROLLER_DOWNGRADE_SAFEMODE_THRESHOLD: u64 = 5000;
+
+/// 各控制器等级允许建造的建筑数量，下标为控制器等级
+pub const CONTROLLER_STRUCTURES: [(&str, [u32; 9]); 9] = [
+    ("spawn", [0, 1, 1, 1, 1, 1, 1, 2, 3]),
+    ("extension", [0, 0, 5, 10, 20, 30, 40, 50, 60]),
+    ("lab", [0, 0, 0, 0, 0, 0, 3, 6, 10]),
+    ("tower", [0, 0, 0, 1, 1, 2, 2, 3, 6]),
+    ("link", [0, 0, 0, 0, 0, 2, 3, 4, 6]),
+    ("factory", [0, 0, 0, 0, 0, 0, 0, 1, 1]),
+    ("nuker", [0, 0, 0, 0, 0, 0, 0, 0, 1]),
+    ("observer", [0, 0, 0, 0, 0, 0, 0, 0, 1]),
+    ("powerSpawn", [0, 0, 0, 0, 0, 0, 0, 0, 1]),
+];
+
+/// 塔的能量容量
+pub const TOWER_CAPACITY: i32 = 1000;
+
+/// 核弹发射所需能量
+pub const NUKER_ENERGY_CAPACITY: i32 = 300_000;
+
+/// 核弹发射所需 G
+pub const NUKER_GHODIUM_CAPACITY: i32 = 5000;
+
+/// 超能力名称，下标为超能力 id
+pub const POWER_NAMES: [&str; 20] = [
+    "",
+    "GENERATE_OPS",
+    "OPERATE_SPAWN",
+    "OPERATE_TOWER",
+    "OPERATE_STORAGE",
+    "OPERATE_LAB",
+    "OPERATE_EXTENSION",
+    "OPERATE_OBSERVER",
+    "OPERATE_TERMINAL",
+    "DISRUPT_SPAWN",
+    "DISRUPT_TOWER",
+    "DISRUPT_SOURCE",
+    "SHIELD",
+    "REGEN_SOURCE",
+    "REGEN_MINERAL",
+    "DISRUPT_TERMINAL",
+    "OPERATE_POWER",
+    "FORTIFY",
+    "OPERATE_CONTROLLER",
+    "OPERATE_FACTORY",
+];
+
 /// 资源颜色映射
 pub fn res_color_map() -> HashMap<&'static str, &'static str> {
     let mut map = HashMap::new();
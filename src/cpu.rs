@@ -0,0 +1,173 @@
+use crate::{
+    config::{Config, StatsTarget},
+    history::{self, HistoryRecord},
+    ingest::{self, CpuStats},
+    memory::query_stats,
+    metrics::RenderTimer,
+    res::check_shard,
+    utils::{draw_line_chart, parse_color, render_png},
+};
+use screeps_rust_api::{ScreepsError, ScreepsResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+/// 一次 CPU 和内存采样
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug, ToSchema)]
+pub struct CpuRecord {
+    /// 使用的 CPU
+    pub cpu: Option<f64>,
+    pub bucket: Option<f64>,
+    /// Memory 大小（字节）
+    pub memory: Option<u64>,
+}
+
+/// 从 Memory 中的统计数据提取 CPU 和内存，缺少的字段使用 socket 推送的数据
+/// 支持 `{ cpu: { used, bucket }, memory: { used } }`，也支持 `cpu`、`bucket`、`memory` 直接为数值
+fn extract_record(stats: &Value, live: Option<CpuStats>) -> CpuRecord {
+    let number = |pointers: &[&str]| {
+        pointers
+            .iter()
+            .find_map(|pointer| stats.pointer(pointer).and_then(Value::as_f64))
+    };
+    CpuRecord {
+        cpu: number(&["/cpu/used", "/cpu"]).or(live.map(|l| l.cpu)),
+        bucket: number(&["/cpu/bucket", "/bucket"]),
+        memory: number(&["/memory/used", "/memory"])
+            .map(|m| m as u64)
+            .or(live.map(|l| l.memory)),
+    }
+}
+
+/// 采集一个玩家的 CPU 和内存，写入历史记录
+async fn collect_cpu(client: &reqwest::Client, target: &StatsTarget) {
+    let stats = match query_stats(client, target).await {
+        Ok(stats) => stats,
+        Err(e) => {
+            eprintln!("Failed to read stats of {}: {}", target.username, e);
+            Value::Null
+        }
+    };
+    let record = extract_record(&stats, ingest::latest_cpu(&target.username));
+    if record == CpuRecord::default() {
+        return;
+    }
+    let key = history::player_key(&target.username, &target.shard);
+    if let Err(e) = history::append("cpu", &key, &record) {
+        eprintln!("Failed to save cpu history for {}: {}", target.username, e);
+    }
+}
+
+/// 启动 CPU 采集任务，没有配置采集目标或没有开启历史记录时不启动
+pub fn spawn_cpu_job(config: Arc<Config>) -> Option<JoinHandle<()>> {
+    if config.cpu.targets.is_empty() {
+        return None;
+    }
+    if !history::is_enabled() {
+        eprintln!("CPU collection is configured but HISTORY_ENABLED is off");
+        return None;
+    }
+    Some(tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut interval = tokio::time::interval(Duration::from_secs(config.cpu.interval));
+        loop {
+            interval.tick().await;
+            for target in &config.cpu.targets {
+                collect_cpu(&client, target).await;
+            }
+        }
+    }))
+}
+
+/// 查询玩家的 CPU 和内存历史
+/// 参数：
+/// - since: 起始 unix 时间戳（秒），`None` 表示不限制
+/// - until: 结束 unix 时间戳（秒），`None` 表示不限制
+pub fn query_cpu(
+    username: &str,
+    shard: &str,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> ScreepsResult<Vec<HistoryRecord<CpuRecord>>> {
+    check_shard(shard)?;
+    history::load("cpu", &history::player_key(username, shard), since, until)
+        .map_err(|e| ScreepsError::Api(format!("读取历史记录失败: {}", e)))
+}
+
+/// 绘制 CPU、bucket 和内存的折线图，返回 PNG 图片
+pub fn draw_cpu_image(
+    username: &str,
+    shard: &str,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let records = query_cpu(username, shard, since, until)?;
+    let _timer = RenderTimer::new("cpu");
+
+    let series = |value: fn(&CpuRecord) -> Option<f64>| -> Vec<(i64, f64)> {
+        records
+            .iter()
+            .filter_map(|r| value(&r.value).map(|v| (r.time, v)))
+            .collect()
+    };
+    render_png((800, 600), |root| {
+        root.fill(&parse_color("#2b2b2b").unwrap())?;
+        let panels = root.split_evenly((3, 1));
+        draw_line_chart(
+            &panels[0],
+            &format!("{} {} cpu", username, shard),
+            &series(|r| r.cpu),
+            "rgb(108, 240, 169)",
+        );
+        draw_line_chart(
+            &panels[1],
+            "bucket",
+            &series(|r| r.bucket),
+            "rgb(255,242,0)",
+        );
+        draw_line_chart(
+            &panels[2],
+            "memory (KB)",
+            &series(|r| r.memory.map(|m| m as f64 / 1024.0)),
+            "rgb(80,175,255)",
+        );
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_extract_record() {
+        let stats = json!({ "cpu": { "used": 12.5, "bucket": 10000 }, "memory": { "used": 2048 } });
+        assert_eq!(
+            extract_record(&stats, None),
+            CpuRecord {
+                cpu: Some(12.5),
+                bucket: Some(10000.0),
+                memory: Some(2048),
+            }
+        );
+
+        let stats = json!({ "bucket": 9000 });
+        let live = CpuStats {
+            cpu: 20.0,
+            memory: 4096,
+            time: 0,
+        };
+        assert_eq!(
+            extract_record(&stats, Some(live)),
+            CpuRecord {
+                cpu: Some(20.0),
+                bucket: Some(9000.0),
+                memory: Some(4096),
+            }
+        );
+        assert_eq!(extract_record(&Value::Null, None), CpuRecord::default());
+    }
+}
@@ -0,0 +1,117 @@
+use crate::res::{fetch_room_objects, query_game_times, query_user_id, query_user_rooms};
+use screeps_rust_api::{RoomObject, ScreepsApi, ScreepsResult};
+use serde::Serialize;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// 一组 creep 的统计
+#[derive(Serialize, Default, ToSchema)]
+pub struct CreepSummary {
+    pub count: u32,
+    /// 每种身体部件的数量
+    pub body: HashMap<String, u32>,
+    /// 所有 creep 剩余寿命之和
+    pub ttl: u64,
+    /// 携带的资源
+    pub store: HashMap<String, i32>,
+}
+
+/// 房间 creep 统计
+#[derive(Serialize, ToSchema)]
+pub struct RoomCreeps {
+    pub shard: String,
+    pub room: String,
+    pub total: CreepSummary,
+    /// 按名称前缀（角色）分组的统计，未开启分组时为 `None`
+    pub groups: Option<HashMap<String, CreepSummary>>,
+}
+
+/// 取 creep 名称的前缀作为角色名，如 `harvester_W1N1_12` 和 `harvester12` 都为 `harvester`
+fn name_prefix(name: &str) -> &str {
+    let end = name
+        .find(|c: char| c == '_' || c == '-' || c == ':' || c.is_ascii_digit())
+        .unwrap_or(name.len());
+    if end == 0 { name } else { &name[..end] }
+}
+
+/// 查询玩家各房间的 creep 统计
+/// 参数：
+/// - username: 玩家名称
+/// - target_shard: 目标 shard，传 `all` 表示所有 shard
+/// - group_by_prefix: 是否按名称前缀分组
+pub async fn query_creeps(
+    api: &ScreepsApi,
+    username: &str,
+    target_shard: &str,
+    group_by_prefix: bool,
+) -> ScreepsResult<Vec<RoomCreeps>> {
+    let user_id = query_user_id(api, username).await?;
+    let user_rooms = query_user_rooms(api, &user_id).await?;
+    let room_objects_list = fetch_room_objects(api, &user_rooms, target_shard).await?;
+    let game_times = query_game_times(api, &room_objects_list).await?;
+
+    let mut result = Vec::new();
+    for room_objects in room_objects_list {
+        let game_time = game_times[&room_objects.shard];
+        let mut total = CreepSummary::default();
+        let mut groups: HashMap<String, CreepSummary> = HashMap::new();
+        for room_object in room_objects.objects {
+            // 只统计自己的 creep
+            let RoomObject::Creep(creep) = room_object else {
+                continue;
+            };
+            if creep.user.as_deref() != Some(user_id.as_str()) {
+                continue;
+            }
+
+            let ttl = creep
+                .age_time
+                .map(|t| t.saturating_sub(game_time))
+                .unwrap_or(0);
+            let mut summaries = vec![&mut total];
+            if group_by_prefix {
+                summaries.push(
+                    groups
+                        .entry(name_prefix(&creep.name).to_string())
+                        .or_default(),
+                );
+            }
+            for summary in summaries {
+                summary.count += 1;
+                summary.ttl += ttl;
+                for part in creep.body.iter() {
+                    *summary.body.entry(part.r#type.to_string()).or_insert(0) += 1;
+                }
+                for (resource_type, amount) in creep.store.iter() {
+                    let amount = amount.unwrap_or(0);
+                    if amount > 0 {
+                        *summary.store.entry(resource_type.to_string()).or_insert(0) += amount;
+                    }
+                }
+            }
+        }
+
+        result.push(RoomCreeps {
+            shard: room_objects.shard,
+            room: room_objects.room,
+            total,
+            groups: group_by_prefix.then_some(groups),
+        });
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_prefix() {
+        assert_eq!(name_prefix("harvester_W1N1_12"), "harvester");
+        assert_eq!(name_prefix("upgrader-3"), "upgrader");
+        assert_eq!(name_prefix("builder12"), "builder");
+        assert_eq!(name_prefix("claimer"), "claimer");
+        assert_eq!(name_prefix("123"), "123");
+    }
+}
@@ -0,0 +1,129 @@
+use chrono::{Datelike, Timelike};
+
+/// cron 表达式，格式为 `分 时 日 月 周`
+/// 每个字段支持 `*`、`*/n`、`a-b`、`a-b/n` 和逗号分隔的列表，周日为 0 或 7
+pub struct CronSchedule {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    /// 日字段是否为 `*`
+    any_day: bool,
+    /// 周字段是否为 `*`
+    any_weekday: bool,
+}
+
+/// 解析 cron 的一个字段，返回 [0, max] 内每个值是否匹配
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<bool>, String> {
+    let mut values = vec![false; max as usize + 1];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .map_err(|_| format!("cron 步长错误: {}", part))?,
+            ),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(format!("cron 步长不能为 0: {}", part));
+        }
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                start
+                    .parse()
+                    .map_err(|_| format!("cron 字段错误: {}", part))?,
+                end.parse()
+                    .map_err(|_| format!("cron 字段错误: {}", part))?,
+            )
+        } else {
+            let value = range
+                .parse()
+                .map_err(|_| format!("cron 字段错误: {}", part))?;
+            (value, value)
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("cron 字段超出范围: {}", part));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            values[value as usize] = true;
+        }
+    }
+    Ok(values)
+}
+
+impl CronSchedule {
+    /// 解析 cron 表达式
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("cron 表达式需要 5 个字段: {}", expr));
+        }
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        // 7 和 0 都表示周日
+        if weekdays[7] {
+            weekdays[0] = true;
+        }
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    /// 判断时间是否匹配，精确到分钟
+    /// 与标准 cron 一致，日和周都有限制时满足其一即可
+    pub fn matches<T: Datelike + Timelike>(&self, time: &T) -> bool {
+        let day = self.days[time.day() as usize];
+        let weekday = self.weekdays[time.weekday().num_days_from_sunday() as usize];
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        };
+        self.minutes[time.minute() as usize]
+            && self.hours[time.hour() as usize]
+            && self.months[time.month() as usize]
+            && day_matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_cron_schedule() {
+        // 2025-01-06 是周一
+        let time = NaiveDate::from_ymd_opt(2025, 1, 6)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap();
+
+        assert!(CronSchedule::parse("* * * * *").unwrap().matches(&time));
+        assert!(CronSchedule::parse("30 9 * * *").unwrap().matches(&time));
+        assert!(
+            CronSchedule::parse("*/15 8-10 * * 1-5")
+                .unwrap()
+                .matches(&time)
+        );
+        assert!(!CronSchedule::parse("0 9 * * *").unwrap().matches(&time));
+        assert!(!CronSchedule::parse("30 9 * * 0,7").unwrap().matches(&time));
+        // 日和周都有限制时满足其一即可
+        assert!(CronSchedule::parse("30 9 1 * 1").unwrap().matches(&time));
+        assert!(!CronSchedule::parse("30 9 1 * 2").unwrap().matches(&time));
+
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+    }
+}
@@ -0,0 +1,214 @@
+use crate::{
+    constants::{NUKER_ENERGY_CAPACITY, NUKER_GHODIUM_CAPACITY, TOWER_CAPACITY},
+    metrics::RenderTimer,
+    res::{query_game_times, query_room_objects},
+    utils::{draw_bar, draw_res_text, format_number, parse_color, render_png, store_amount},
+};
+use chrono::prelude::*;
+use screeps_rust_api::{RoomObject, ScreepsApi, ScreepsResult};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// 血量统计
+#[derive(Serialize, Default, ToSchema)]
+pub struct HitsStats {
+    pub count: u32,
+    pub min: u32,
+    pub avg: u32,
+    pub total: u64,
+}
+
+impl HitsStats {
+    fn from_hits(hits: &[u32]) -> Self {
+        if hits.is_empty() {
+            return HitsStats::default();
+        }
+        let total: u64 = hits.iter().map(|h| *h as u64).sum();
+        HitsStats {
+            count: hits.len() as u32,
+            min: *hits.iter().min().unwrap(),
+            avg: (total / hits.len() as u64) as u32,
+            total,
+        }
+    }
+}
+
+/// 核弹状态
+#[derive(Serialize, ToSchema)]
+pub struct NukerStatus {
+    pub energy: i32,
+    pub ghodium: i32,
+    /// 剩余冷却 tick
+    pub cooldown: u64,
+    /// 资源已装满且冷却完毕
+    pub ready: bool,
+}
+
+/// 房间防御状态
+#[derive(Serialize, ToSchema)]
+pub struct RoomDefense {
+    pub shard: String,
+    pub room: String,
+    pub ramparts: HitsStats,
+    pub walls: HitsStats,
+    pub tower_count: u32,
+    pub tower_energy: i32,
+    pub tower_energy_capacity: i32,
+    pub nuker: Option<NukerStatus>,
+}
+
+/// 查询玩家各房间的防御状态
+/// 参数：
+/// - username: 玩家名称
+/// - target_shard: 目标 shard，传 `all` 表示所有 shard
+pub async fn query_defense(
+    api: &ScreepsApi,
+    username: &str,
+    target_shard: &str,
+) -> ScreepsResult<Vec<RoomDefense>> {
+    let room_objects_list = query_room_objects(api, username, target_shard).await?;
+    let game_times = query_game_times(api, &room_objects_list).await?;
+
+    let mut result = Vec::new();
+    for room_objects in room_objects_list {
+        let game_time = game_times[&room_objects.shard];
+        let mut rampart_hits = Vec::new();
+        let mut wall_hits = Vec::new();
+        let mut tower_count = 0;
+        let mut tower_energy = 0;
+        let mut nuker = None;
+        for room_object in &room_objects.objects {
+            match room_object {
+                RoomObject::Rampart(rampart) => rampart_hits.push(rampart.hits.unwrap_or(0)),
+                RoomObject::ConstructedWall(wall) => wall_hits.push(wall.hits.unwrap_or(0)),
+                RoomObject::Tower(tower) => {
+                    tower_count += 1;
+                    tower_energy += store_amount(&tower.store, "energy");
+                }
+                RoomObject::Nuker(n) => {
+                    let energy = store_amount(&n.store, "energy");
+                    let ghodium = store_amount(&n.store, "G");
+                    let cooldown = n
+                        .cooldown_time
+                        .map(|t| t.saturating_sub(game_time))
+                        .unwrap_or(0);
+                    nuker = Some(NukerStatus {
+                        energy,
+                        ghodium,
+                        cooldown,
+                        ready: energy >= NUKER_ENERGY_CAPACITY
+                            && ghodium >= NUKER_GHODIUM_CAPACITY
+                            && cooldown == 0,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        result.push(RoomDefense {
+            shard: room_objects.shard,
+            room: room_objects.room,
+            ramparts: HitsStats::from_hits(&rampart_hits),
+            walls: HitsStats::from_hits(&wall_hits),
+            tower_count,
+            tower_energy,
+            tower_energy_capacity: tower_count as i32 * TOWER_CAPACITY,
+            nuker,
+        });
+    }
+
+    Ok(result)
+}
+
+/// 绘制防御状态图片，每个房间一行，显示 rampart 最低血量和塔能量
+pub async fn draw_defense_image(
+    api: &ScreepsApi,
+    username: &str,
+    target_shard: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut defense = query_defense(api, username, target_shard).await?;
+    let _timer = RenderTimer::new("defense");
+    defense.sort_by(|a, b| (&a.shard, &a.room).cmp(&(&b.shard, &b.room)));
+
+    let row_height = 30;
+    let height = 60 + row_height * defense.len().max(1) as u32;
+    render_png((720, height), |root| {
+        root.fill(&parse_color("#2b2b2b").unwrap())?;
+
+        draw_res_text(root, "room", 10, 10, "#ffffff");
+        draw_res_text(root, "rampart min hits", 130, 10, "#ffffff");
+        draw_res_text(root, "tower energy", 430, 10, "#ffffff");
+
+        // rampart 血量条以所有房间中最高的最低血量为满值
+        let max_hits = defense
+            .iter()
+            .map(|d| d.ramparts.min)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let bar_width = 200;
+        for (i, room) in defense.iter().enumerate() {
+            let y = 35 + row_height * i as u32;
+            draw_res_text(
+                root,
+                &format!("{}/{}", room.shard, room.room),
+                10,
+                y,
+                "#ffffff",
+            );
+
+            let ratio = room.ramparts.min as f64 / max_hits as f64;
+            let color = if ratio < 0.3 {
+                "rgb(224,90,90)"
+            } else {
+                "rgb(108, 240, 169)"
+            };
+            draw_bar(root, 130, y as i32 + 2, bar_width, ratio, color);
+            draw_res_text(
+                root,
+                &format_number(room.ramparts.min as i32),
+                340,
+                y,
+                "#888",
+            );
+
+            let ratio = if room.tower_energy_capacity > 0 {
+                room.tower_energy as f64 / room.tower_energy_capacity as f64
+            } else {
+                0.0
+            };
+            draw_bar(root, 430, y as i32 + 2, bar_width, ratio, "rgb(255,242,0)");
+            draw_res_text(
+                root,
+                &format!("{} x{}", format_number(room.tower_energy), room.tower_count),
+                640,
+                y,
+                "#888",
+            );
+        }
+
+        // 当前时间
+        let now: DateTime<Local> = Local::now();
+        let time_str = now.format("%Y/%m/%d %H:%M:%S").to_string();
+        draw_res_text(root, &time_str, 560, height - 20, "#888");
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hits_stats() {
+        let stats = HitsStats::from_hits(&[100, 300, 200]);
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.min, 100);
+        assert_eq!(stats.avg, 200);
+        assert_eq!(stats.total, 600);
+
+        let stats = HitsStats::from_hits(&[]);
+        assert_eq!(stats.count, 0);
+        assert_eq!(stats.min, 0);
+    }
+}
@@ -0,0 +1,105 @@
+use axum::http::StatusCode;
+use screeps_rust_api::ScreepsError;
+
+/// 非上游错误的错误码
+/// 查询函数统一返回 `ScreepsError::Api`，错误码以 `[code] ` 前缀保存在错误信息中，
+/// 接口返回前由 `classify` 解析并去掉前缀，没有前缀的错误视为上游错误
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ErrorCode {
+    /// 玩家或资源不存在
+    NotFound,
+    /// 请求参数错误
+    BadRequest,
+    /// token 无效
+    Unauthorized,
+    /// 无权访问
+    Forbidden,
+    /// 服务未开启相关功能
+    Unavailable,
+}
+
+const CODES: [ErrorCode; 5] = [
+    ErrorCode::NotFound,
+    ErrorCode::BadRequest,
+    ErrorCode::Unauthorized,
+    ErrorCode::Forbidden,
+    ErrorCode::Unavailable,
+];
+
+impl ErrorCode {
+    /// v1 接口返回的错误码
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::Unavailable => "unavailable",
+        }
+    }
+
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn prefix(self) -> String {
+        format!("[{}] ", self.code())
+    }
+}
+
+/// 创建带错误码的错误
+pub fn error(code: ErrorCode, message: impl Into<String>) -> ScreepsError {
+    ScreepsError::Api(format!("{}{}", code.prefix(), message.into()))
+}
+
+/// 解析错误信息中的错误码，返回 (错误码, 去掉前缀的错误信息)，没有错误码时为上游错误
+pub fn parse(message: &str) -> (Option<ErrorCode>, String) {
+    for code in CODES {
+        let prefix = code.prefix();
+        if let Some(start) = message.find(&prefix) {
+            let rest = &message[start + prefix.len()..];
+            return (Some(code), format!("{}{}", &message[..start], rest));
+        }
+    }
+    (None, message.to_string())
+}
+
+/// 解析查询错误
+pub fn classify(e: &ScreepsError) -> (Option<ErrorCode>, String) {
+    match e {
+        ScreepsError::Api(message) => parse(message),
+        #[allow(unreachable_patterns)]
+        e => (None, e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let e = error(ErrorCode::NotFound, "玩家不存在");
+        assert_eq!(
+            classify(&e),
+            (Some(ErrorCode::NotFound), "玩家不存在".to_string())
+        );
+        assert_eq!(
+            parse("Api error: [forbidden] 无权访问"),
+            (
+                Some(ErrorCode::Forbidden),
+                "Api error: 无权访问".to_string()
+            )
+        );
+        assert_eq!(
+            classify(&ScreepsError::Api("请求超时".to_string())),
+            (None, "请求超时".to_string())
+        );
+    }
+}
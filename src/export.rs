@@ -0,0 +1,313 @@
+use crate::{
+    history::{self, HistoryRecord, HistorySource},
+    res::{check_shard, query_res_rows},
+};
+use chrono::DateTime;
+use rust_xlsxwriter::Workbook;
+use screeps_rust_api::{ScreepsApi, ScreepsError, ScreepsResult};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// 导出格式
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+    Xlsx,
+}
+
+/// 导出的表格，每行的值与表头一一对应
+pub struct Table {
+    pub headers: Vec<&'static str>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+/// 导出的文件
+pub struct ExportFile {
+    pub content_type: &'static str,
+    pub file_name: String,
+    pub body: Vec<u8>,
+}
+
+impl ExportFile {
+    /// 下载时的 `Content-Disposition`
+    /// `filename` 只保留字母、数字、`.`、`_` 和 `-`，其他字符替换为 `_`；
+    /// `filename*` 按 RFC 6266 使用 UTF-8 百分号编码保留原始文件名
+    pub fn content_disposition(&self) -> String {
+        let mut fallback = String::new();
+        let mut encoded = String::new();
+        for c in self.file_name.chars() {
+            let safe = c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-');
+            fallback.push(if safe { c } else { '_' });
+        }
+        for byte in self.file_name.bytes() {
+            if byte.is_ascii_alphanumeric() || matches!(byte, b'.' | b'_' | b'-') {
+                encoded.push(byte as char);
+            } else {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        format!(
+            "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+            fallback, encoded
+        )
+    }
+}
+
+/// 转换为 CSV 字段，包含逗号、引号或换行时加引号
+fn csv_field(value: &Value) -> String {
+    let text = match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+impl Table {
+    /// 转换为 CSV
+    pub fn to_csv(&self) -> String {
+        let mut out = self.headers.join(",");
+        out.push('\n');
+        for row in &self.rows {
+            let fields: Vec<_> = row.iter().map(csv_field).collect();
+            out.push_str(&fields.join(","));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// 转换为 NDJSON，每行一个 JSON 对象
+    pub fn to_ndjson(&self) -> String {
+        let mut out = String::new();
+        for row in &self.rows {
+            let object: serde_json::Map<String, Value> = self
+                .headers
+                .iter()
+                .map(|h| h.to_string())
+                .zip(row.iter().cloned())
+                .collect();
+            out.push_str(&Value::Object(object).to_string());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// 转换为 xlsx
+    pub fn to_xlsx(&self) -> Result<Vec<u8>, rust_xlsxwriter::XlsxError> {
+        let mut workbook = Workbook::new();
+        let worksheet = workbook.add_worksheet();
+        for (col, header) in self.headers.iter().enumerate() {
+            worksheet.write(0, col as u16, *header)?;
+        }
+        for (row_index, row) in self.rows.iter().enumerate() {
+            let row_index = row_index as u32 + 1;
+            for (col, value) in row.iter().enumerate() {
+                match value {
+                    Value::Number(n) => {
+                        worksheet.write(row_index, col as u16, n.as_f64().unwrap_or(0.0))?
+                    }
+                    Value::String(s) => worksheet.write(row_index, col as u16, s.as_str())?,
+                    Value::Null => continue,
+                    other => worksheet.write(row_index, col as u16, other.to_string().as_str())?,
+                };
+            }
+        }
+        workbook.save_to_buffer()
+    }
+
+    /// 按格式生成文件
+    pub fn export(&self, format: ExportFormat, name: &str) -> ScreepsResult<ExportFile> {
+        Ok(match format {
+            ExportFormat::Csv => ExportFile {
+                content_type: "text/csv; charset=utf-8",
+                file_name: format!("{}.csv", name),
+                body: self.to_csv().into_bytes(),
+            },
+            ExportFormat::Ndjson => ExportFile {
+                content_type: "application/x-ndjson",
+                file_name: format!("{}.ndjson", name),
+                body: self.to_ndjson().into_bytes(),
+            },
+            ExportFormat::Xlsx => ExportFile {
+                content_type: "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+                file_name: format!("{}.xlsx", name),
+                body: self
+                    .to_xlsx()
+                    .map_err(|e| ScreepsError::Api(format!("生成 xlsx 失败: {}", e)))?,
+            },
+        })
+    }
+}
+
+/// 导出玩家当前的资源，每行为 (shard, 房间, 建筑, 资源, 数量)
+pub async fn export_res(
+    api: &ScreepsApi,
+    username: &str,
+    target_shard: &str,
+    format: ExportFormat,
+) -> ScreepsResult<ExportFile> {
+    let mut rows = query_res_rows(api, username, target_shard).await?;
+    rows.sort_by(|a, b| {
+        (&a.shard, &a.room, &a.structure, &a.resource).cmp(&(
+            &b.shard,
+            &b.room,
+            &b.structure,
+            &b.resource,
+        ))
+    });
+    let table = Table {
+        headers: vec!["shard", "room", "structure", "resource", "amount"],
+        rows: rows
+            .into_iter()
+            .map(|row| {
+                vec![
+                    row.shard.into(),
+                    row.room.into(),
+                    row.structure.into(),
+                    row.resource.into(),
+                    row.amount.into(),
+                ]
+            })
+            .collect(),
+    };
+    table.export(format, &format!("{}_{}_res", username, target_shard))
+}
+
+/// 读取历史记录，每行为 (时间, shard, 字段, 数值)
+fn history_rows<T: DeserializeOwned + Into<Value>>(
+    kind: &str,
+    username: &str,
+    shards: &[String],
+    fields: &[String],
+    since: Option<i64>,
+    until: Option<i64>,
+) -> std::io::Result<Vec<Vec<Value>>> {
+    let mut rows = Vec::new();
+    for shard in shards {
+        let records: Vec<HistoryRecord<BTreeMap<String, T>>> =
+            history::load(kind, &history::player_key(username, shard), since, until)?;
+        for record in records {
+            let time = DateTime::from_timestamp(record.time, 0)
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_default();
+            for (field, value) in record.value {
+                if !fields.is_empty() && !fields.contains(&field) {
+                    continue;
+                }
+                rows.push(vec![
+                    time.clone().into(),
+                    shard.clone().into(),
+                    field.into(),
+                    value.into(),
+                ]);
+            }
+        }
+    }
+    Ok(rows)
+}
+
+/// 导出玩家的资源或统计数据历史，每行为 (时间, shard, 资源或字段, 数值)，需要开启历史记录
+/// 参数：
+/// - fields: 只导出这些资源或字段，为空时导出所有
+/// - since: 起始 unix 时间戳（秒），`None` 表示不限制
+/// - until: 结束 unix 时间戳（秒），`None` 表示不限制
+pub fn export_history(
+    source: HistorySource,
+    username: &str,
+    target_shard: &str,
+    fields: &[String],
+    since: Option<i64>,
+    until: Option<i64>,
+    format: ExportFormat,
+) -> ScreepsResult<ExportFile> {
+    check_shard(target_shard)?;
+    let io_error = |e: std::io::Error| ScreepsError::Api(format!("读取历史记录失败: {}", e));
+
+    // 找到需要导出的 shard
+    let shards: Vec<String> = if target_shard == "all" {
+        history::player_shards(source.kind(), username).map_err(io_error)?
+    } else {
+        vec![target_shard.to_string()]
+    };
+
+    let (headers, rows) = match source {
+        HistorySource::Res => (
+            vec!["time", "shard", "resource", "amount"],
+            history_rows::<i32>("res", username, &shards, fields, since, until),
+        ),
+        HistorySource::Stats => (
+            vec!["time", "shard", "field", "value"],
+            history_rows::<f64>("stats", username, &shards, fields, since, until),
+        ),
+    };
+    let mut rows = rows.map_err(io_error)?;
+    // 按时间排序，同一时间按 shard 排序
+    rows.sort_by(|a, b| (a[0].as_str(), a[1].as_str()).cmp(&(b[0].as_str(), b[1].as_str())));
+
+    let table = Table { headers, rows };
+    table.export(
+        format,
+        &format!("{}_{}_{}_history", username, target_shard, source.kind()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn table() -> Table {
+        Table {
+            headers: vec!["room", "resource", "amount"],
+            rows: vec![
+                vec![json!("W1N1"), json!("energy"), json!(1000)],
+                vec![json!("W2N1"), json!("a,\"b\""), json!(5)],
+            ],
+        }
+    }
+
+    #[test]
+    fn test_to_csv() {
+        assert_eq!(
+            table().to_csv(),
+            "room,resource,amount\nW1N1,energy,1000\nW2N1,\"a,\"\"b\"\"\",5\n"
+        );
+    }
+
+    #[test]
+    fn test_to_ndjson() {
+        let text = table().to_ndjson();
+        let lines: Vec<Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                json!({ "room": "W1N1", "resource": "energy", "amount": 1000 }),
+                json!({ "room": "W2N1", "resource": "a,\"b\"", "amount": 5 }),
+            ]
+        );
+    }
+    #[test]
+    fn test_content_disposition() {
+        let file = ExportFile {
+            content_type: "text/csv",
+            file_name: "张\"三_all_res.csv".to_string(),
+            body: Vec::new(),
+        };
+        assert_eq!(
+            file.content_disposition(),
+            "attachment; filename=\"____all_res.csv\"; filename*=UTF-8''%E5%BC%A0%22%E4%B8%89_all_res.csv"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{
+    config::{Config, HttpConfig},
+    res::res_cache_ttl,
+    tokens,
+};
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+    time::Duration,
+};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// 跨域配置，未配置允许的来源时返回 None
+/// `*` 允许所有来源，否则只允许列出的来源
+pub fn cors_layer(config: &HttpConfig) -> Option<CorsLayer> {
+    if config.cors_origins.is_empty() {
+        return None;
+    }
+    let origin = if config.cors_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            config
+                .cors_origins
+                .iter()
+                .filter_map(|origin| origin.parse().ok()),
+        )
+    };
+    Some(
+        CorsLayer::new()
+            .allow_origin(origin)
+            .allow_methods([Method::GET, Method::POST, Method::DELETE])
+            .allow_headers([
+                header::AUTHORIZATION,
+                header::CONTENT_TYPE,
+                header::IF_NONE_MATCH,
+                HeaderName::from_static("x-api-key"),
+                HeaderName::from_static(tokens::TOKEN_HEADER),
+            ])
+            .expose_headers([header::ETAG, header::RETRY_AFTER])
+            .max_age(Duration::from_secs(3600)),
+    )
+}
+
+/// 计算响应内容的 ETag
+/// JSON 响应中的 `meta` 包含请求耗时等每次都不同的信息，不参与计算
+/// 使用弱 ETag：内容相同但 `meta` 或压缩编码不同的响应字节并不相同
+fn etag(body: &Bytes) -> String {
+    let mut hasher = DefaultHasher::new();
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Object(mut object)) => {
+            object.remove("meta");
+            serde_json::Value::Object(object)
+                .to_string()
+                .hash(&mut hasher);
+        }
+        _ => body.hash(&mut hasher),
+    }
+    format!("W/\"{:016x}\"", hasher.finish())
+}
+
+/// 判断 `If-None-Match` 是否与 ETag 匹配，使用弱比较，忽略双方的 `W/` 前缀
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    fn opaque(tag: &str) -> &str {
+        tag.strip_prefix("W/").unwrap_or(tag)
+    }
+    let etag = opaque(etag);
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || opaque(tag) == etag)
+}
+
+/// 为成功的响应添加 `ETag` 和 `Cache-Control`，`If-None-Match` 匹配时返回 304
+/// 缓存时间与资源查询缓存时间相同，开启认证时只允许客户端缓存
+pub async fn etag_middleware(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(body) = to_bytes(body, usize::MAX).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let etag = etag(&body);
+    let cache_control = format!(
+        "{}, max-age={}",
+        if config.auth.enabled {
+            "private"
+        } else {
+            "public"
+        },
+        res_cache_ttl().as_secs()
+    );
+    parts
+        .headers
+        .insert(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+    parts.headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_str(&cache_control).unwrap(),
+    );
+
+    if if_none_match
+        .as_ref()
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| etag_matches(value, &etag))
+    {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(header::CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_etag() {
+        let a = etag(&Bytes::from(
+            r#"{"data":{"shard3":{"energy":1}},"meta":{"duration_ms":3}}"#,
+        ));
+        let b = etag(&Bytes::from(
+            r#"{"data":{"shard3":{"energy":1}},"meta":{"duration_ms":8}}"#,
+        ));
+        let c = etag(&Bytes::from(
+            r#"{"data":{"shard3":{"energy":2}},"meta":{"duration_ms":8}}"#,
+        ));
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert!(a.starts_with("W/\""));
+        assert_ne!(
+            etag(&Bytes::from_static(b"\x89PNG1")),
+            etag(&Bytes::from_static(b"\x89PNG2"))
+        );
+
+        assert!(etag_matches(&a, &a));
+        assert!(etag_matches(&format!("\"other\", {}", a), &a));
+        assert!(etag_matches(a.trim_start_matches("W/"), &a));
+        assert!(etag_matches("*", &a));
+        assert!(!etag_matches("\"other\"", &a));
+    }
+}
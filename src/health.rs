@@ -0,0 +1,160 @@
+use crate::{config::Config, history, res::query_game_time};
+use screeps_rust_api::ScreepsApi;
+use serde::Serialize;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+/// 上游检查的超时时间
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 正在运行的后台任务，(任务名称, 任务句柄)
+pub type Jobs = Arc<Vec<(&'static str, JoinHandle<()>)>>;
+
+/// 单项检查结果
+#[derive(Serialize, ToSchema)]
+pub struct Check {
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+impl Check {
+    fn from_result(result: Result<(), String>) -> Self {
+        Check {
+            ok: result.is_ok(),
+            error: result.err(),
+        }
+    }
+}
+
+/// 就绪检查结果
+#[derive(Serialize, ToSchema)]
+pub struct Readiness {
+    pub ready: bool,
+    /// 上游 Screeps API 是否可访问
+    pub upstream: Check,
+    /// data 目录是否可写
+    pub data_dir: Check,
+    /// 后台任务是否在运行
+    pub jobs: Check,
+}
+
+/// 版本信息
+#[derive(Serialize, ToSchema)]
+pub struct VersionInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    /// 编译时的 git 提交
+    pub git_hash: &'static str,
+    /// 已启用的功能
+    pub features: Vec<&'static str>,
+}
+
+/// 用于检查上游的 shard，由环境变量 `HEALTH_CHECK_SHARD` 控制，默认 shard3
+fn health_check_shard() -> String {
+    std::env::var("HEALTH_CHECK_SHARD").unwrap_or("shard3".to_string())
+}
+
+/// 检查上游 Screeps API 是否可访问
+async fn check_upstream(api: &ScreepsApi) -> Result<(), String> {
+    match tokio::time::timeout(
+        UPSTREAM_TIMEOUT,
+        query_game_time(api, &health_check_shard()),
+    )
+    .await
+    {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("请求超时".to_string()),
+    }
+}
+
+/// 写入检查文件的序号，并发的检查使用不同的文件
+static PROBE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// 检查 data 目录是否可写
+async fn check_data_dir() -> Result<(), String> {
+    let path = format!(
+        "data/.readyz-{}-{}",
+        std::process::id(),
+        PROBE_ID.fetch_add(1, Ordering::Relaxed)
+    );
+    tokio::fs::write(&path, b"ok")
+        .await
+        .map_err(|e| e.to_string())?;
+    tokio::fs::remove_file(&path)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 检查后台任务是否都在运行
+fn check_jobs(jobs: &[(&'static str, JoinHandle<()>)]) -> Result<(), String> {
+    let stopped: Vec<_> = jobs
+        .iter()
+        .filter(|(_, handle)| handle.is_finished())
+        .map(|(name, _)| *name)
+        .collect();
+    if stopped.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("后台任务已停止: {}", stopped.join(", ")))
+    }
+}
+
+/// 执行就绪检查
+pub async fn check_readiness(api: &ScreepsApi, jobs: &Jobs) -> Readiness {
+    let (upstream, data_dir) = tokio::join!(check_upstream(api), check_data_dir());
+    let upstream = Check::from_result(upstream);
+    let data_dir = Check::from_result(data_dir);
+    let jobs = Check::from_result(check_jobs(jobs));
+    Readiness {
+        ready: upstream.ok && data_dir.ok && jobs.ok,
+        upstream,
+        data_dir,
+        jobs,
+    }
+}
+
+/// 获取版本信息
+pub fn version_info(config: &Config) -> VersionInfo {
+    let mut features = Vec::new();
+    if history::is_enabled() {
+        features.push("history");
+    }
+    if !config.alerts.rules.is_empty() {
+        features.push("alerts");
+    }
+    if !config.reports.is_empty() {
+        features.push("reports");
+    }
+    VersionInfo {
+        name: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("GIT_HASH"),
+        features,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_jobs() {
+        let running = tokio::spawn(std::future::pending::<()>());
+        let stopped = tokio::spawn(async {});
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        assert!(check_jobs(&[("alerts", running)]).is_ok());
+        assert_eq!(
+            check_jobs(&[("reports", stopped)]),
+            Err("后台任务已停止: reports".to_string())
+        );
+    }
+}
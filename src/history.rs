@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
+use utoipa::ToSchema;
+
+/// 一条历史记录
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct HistoryRecord<T> {
+    /// 记录时间，unix 时间戳（秒）
+    pub time: i64,
+    pub value: T,
+}
+
+/// 是否开启历史记录，通过环境变量 `HISTORY_ENABLED` 控制
+pub fn is_enabled() -> bool {
+    std::env::var("HISTORY_ENABLED")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// 将名称编码为安全的文件名，避免路径穿越
+/// 字母、数字、`_` 和 `-` 保持不变，其他字符按 UTF-8 字节编码为 `%XX`，可以还原
+fn encode(name: &str) -> String {
+    let mut result = String::new();
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-' {
+            result.push(byte as char);
+        } else {
+            result.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    result
+}
+
+/// 还原编码后的文件名，格式错误时返回 None
+fn decode(name: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut chars = name.bytes();
+    while let Some(byte) = chars.next() {
+        if byte == b'%' {
+            let hex = [chars.next()?, chars.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// 按玩家和 shard 保存的历史记录来源
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HistorySource {
+    /// 资源数量
+    #[default]
+    Res,
+    /// 从 Memory 或内存段采集的统计数据
+    Stats,
+}
+
+impl HistorySource {
+    /// 记录类型
+    pub fn kind(self) -> &'static str {
+        match self {
+            HistorySource::Res => "res",
+            HistorySource::Stats => "stats",
+        }
+    }
+}
+
+/// 按玩家和 shard 保存的记录键，如 `player/shard3`
+/// shard 名称不包含 `/`，所以按最后一个 `/` 可以区分玩家名称和 shard
+pub fn player_key(username: &str, shard: &str) -> String {
+    format!("{}/{}", username, shard)
+}
+
+/// 玩家有指定类型历史记录的 shard
+pub fn player_shards(kind: &str, username: &str) -> std::io::Result<Vec<String>> {
+    let prefix = player_key(username, "");
+    Ok(list_keys(kind)?
+        .into_iter()
+        .filter_map(|key| key.strip_prefix(&prefix).map(|s| s.to_string()))
+        // 排除名称以该玩家名称加 `/` 开头的其他玩家
+        .filter(|shard| !shard.contains('/'))
+        .collect())
+}
+
+/// 历史记录目录：data/history/{kind}
+fn history_dir(kind: &str) -> PathBuf {
+    PathBuf::from("data").join("history").join(encode(kind))
+}
+
+/// 历史记录文件路径：data/history/{kind}/{key}.jsonl
+fn history_path(kind: &str, key: &str) -> PathBuf {
+    history_dir(kind).join(format!("{}.jsonl", encode(key)))
+}
+
+/// 列出指定类型的所有记录键
+pub fn list_keys(kind: &str) -> std::io::Result<Vec<String>> {
+    let dir = history_dir(kind);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut keys = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "jsonl")
+            && let Some(key) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(decode)
+        {
+            keys.push(key);
+        }
+    }
+    keys.sort();
+    Ok(keys)
+}
+
+/// 追加一条历史记录
+/// 参数：
+/// - kind: 记录类型，如 `controller`
+/// - key: 记录键，如 `shard3_W1N1`
+pub fn append<T: Serialize>(kind: &str, key: &str, value: &T) -> std::io::Result<()> {
+    let path = history_path(kind, key);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let record = HistoryRecord {
+        time: chrono::Utc::now().timestamp(),
+        value,
+    };
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(&record)?)?;
+    Ok(())
+}
+
+/// 读取历史记录，按时间升序
+/// 参数：
+/// - since: 只返回该时间戳及之后的记录，`None` 表示不限制
+/// - until: 只返回该时间戳及之前的记录，`None` 表示不限制
+pub fn load<T: DeserializeOwned>(
+    kind: &str,
+    key: &str,
+    since: Option<i64>,
+    until: Option<i64>,
+) -> std::io::Result<Vec<HistoryRecord<T>>> {
+    let path = history_path(kind, key);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let file = fs::File::open(path)?;
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        // 跳过损坏的行
+        let Ok(record) = serde_json::from_str::<HistoryRecord<T>>(&line?) else {
+            continue;
+        };
+        if since.is_none_or(|since| record.time >= since)
+            && until.is_none_or(|until| record.time <= until)
+        {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode("player_shard3"), "player_shard3");
+        assert_eq!(encode("../x"), "%2E%2E%2Fx");
+        assert_ne!(encode("张三_shard3"), encode("李四_shard3"));
+        for name in ["player_shard3", "张三_shard3", "a%b", "../x"] {
+            assert_eq!(decode(&encode(name)).as_deref(), Some(name));
+        }
+        assert_eq!(decode("%E5%BC"), None);
+        assert_eq!(decode("%4"), None);
+    }
+
+    #[test]
+    fn test_player_key() {
+        assert_ne!(player_key("a_b", "c"), player_key("a", "b_c"));
+        assert_eq!(player_key("player", "shard3"), "player/shard3");
+    }
+}
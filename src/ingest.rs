@@ -0,0 +1,522 @@
+use crate::{
+    config::Config,
+    error::{ErrorCode, error},
+    res::{query_user_id, query_user_rooms},
+    tokens,
+    utils::decode_gz,
+};
+use chrono::Local;
+use futures::{SinkExt, StreamExt};
+use screeps_rust_api::{RoomObject, ScreepsApi, ScreepsResult};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, LazyLock, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use utoipa::ToSchema;
+
+/// 每个玩家保留的控制台消息条数
+const CONSOLE_SIZE: usize = 100;
+
+/// 一个房间的房间对象，键为对象 id
+struct RoomState {
+    objects: HashMap<String, Value>,
+    updated: Instant,
+}
+
+/// 玩家的 CPU 和内存使用
+#[derive(Serialize, Clone, Copy, ToSchema)]
+pub struct CpuStats {
+    pub cpu: f64,
+    /// Memory 大小（字节）
+    pub memory: u64,
+    /// 收到数据的 unix 时间戳（秒）
+    pub time: i64,
+}
+
+/// 一条控制台消息
+#[derive(Serialize, Clone, ToSchema)]
+pub struct ConsoleLine {
+    pub time: String,
+    pub shard: Option<String>,
+    /// 消息类型：`log` 为 console.log 输出，`result` 为命令执行结果，`error` 为错误
+    pub kind: &'static str,
+    pub message: String,
+}
+
+/// 玩家的实时状态
+#[derive(Serialize, ToSchema)]
+pub struct UserLive {
+    pub cpu: Option<CpuStats>,
+    /// 最近的控制台消息，新的在后
+    pub console: Vec<ConsoleLine>,
+}
+
+/// 内存中的数据模型，由 socket 推送的数据增量更新
+#[derive(Default)]
+struct Model {
+    /// 键为 (shard, 房间)
+    rooms: Mutex<HashMap<(String, String), RoomState>>,
+    /// 键为玩家名称
+    cpu: Mutex<HashMap<String, CpuStats>>,
+    /// 键为玩家名称
+    console: Mutex<HashMap<String, VecDeque<ConsoleLine>>>,
+}
+
+static MODEL: LazyLock<Model> = LazyLock::new(Model::default);
+
+/// 房间数据的有效期，开启接入后设置
+static MAX_AGE: OnceLock<Duration> = OnceLock::new();
+
+/// 需要订阅的数据
+#[derive(Default)]
+pub struct Subscriptions {
+    /// (shard, 房间)
+    pub rooms: Vec<(String, String)>,
+    /// 玩家 id -> 玩家名称
+    pub users: HashMap<String, String>,
+}
+
+impl Subscriptions {
+    /// 需要订阅的频道
+    fn channels(&self) -> Vec<String> {
+        let mut channels: Vec<String> = self
+            .rooms
+            .iter()
+            .map(|(shard, room)| format!("room:{}/{}", shard, room))
+            .collect();
+        for user_id in self.users.keys() {
+            channels.push(format!("user:{}/cpu", user_id));
+            channels.push(format!("user:{}/console", user_id));
+        }
+        channels
+    }
+}
+
+/// 将增量数据合并到原数据，值为 null 的字段会被删除
+fn merge(target: &mut Value, patch: Value) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                if value.is_null() {
+                    target.remove(&key);
+                } else if let Some(old) = target.get_mut(&key) {
+                    merge(old, value);
+                } else {
+                    target.insert(key, value);
+                }
+            }
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+/// 更新房间对象
+fn update_room(shard: &str, room: &str, data: &Value) {
+    let mut rooms = MODEL.rooms.lock().unwrap();
+    let state = rooms
+        .entry((shard.to_string(), room.to_string()))
+        .or_insert_with(|| RoomState {
+            objects: HashMap::new(),
+            updated: Instant::now(),
+        });
+    state.updated = Instant::now();
+    let Some(objects) = data.get("objects").and_then(Value::as_object) else {
+        return;
+    };
+    for (id, patch) in objects {
+        if patch.is_null() {
+            state.objects.remove(id);
+        } else {
+            let object = state
+                .objects
+                .entry(id.clone())
+                .or_insert_with(|| Value::Object(Map::new()));
+            merge(object, patch.clone());
+        }
+    }
+}
+
+/// 清除房间的数据，订阅后服务器首先推送房间的完整数据，之前连接中的数据可能已经过时
+/// 清除后到收到完整数据之前，查询这些房间时使用 HTTP
+fn reset_rooms(rooms: &[(String, String)]) {
+    let mut states = MODEL.rooms.lock().unwrap();
+    for room in rooms {
+        states.remove(room);
+    }
+}
+
+/// 记录控制台消息
+fn update_console(username: &str, data: &Value) {
+    let time = Local::now().to_rfc3339();
+    let shard = data
+        .get("shard")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    let mut lines = Vec::new();
+    if let Some(error) = data.get("error").and_then(Value::as_str) {
+        lines.push(("error", error.to_string()));
+    }
+    if let Some(messages) = data.get("messages") {
+        for (field, kind) in [("log", "log"), ("results", "result")] {
+            let Some(messages) = messages.get(field).and_then(Value::as_array) else {
+                continue;
+            };
+            for message in messages {
+                let message = match message {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                lines.push((kind, message));
+            }
+        }
+    }
+
+    let mut console = MODEL.console.lock().unwrap();
+    let console = console.entry(username.to_string()).or_default();
+    for (kind, message) in lines {
+        if console.len() >= CONSOLE_SIZE {
+            console.pop_front();
+        }
+        console.push_back(ConsoleLine {
+            time: time.clone(),
+            shard: shard.clone(),
+            kind,
+            message,
+        });
+    }
+}
+
+/// 处理一条频道消息，格式为 `[频道, 数据]`
+fn handle_message(text: &str, subscriptions: &Subscriptions) {
+    let Ok(Value::Array(message)) = serde_json::from_str::<Value>(text) else {
+        return;
+    };
+    let [Value::String(channel), data] = message.as_slice() else {
+        return;
+    };
+
+    if let Some(path) = channel.strip_prefix("room:") {
+        if let Some((shard, room)) = path.split_once('/') {
+            update_room(shard, room, data);
+        }
+    } else if let Some(path) = channel.strip_prefix("user:") {
+        let Some((user_id, kind)) = path.split_once('/') else {
+            return;
+        };
+        let Some(username) = subscriptions.users.get(user_id) else {
+            return;
+        };
+        match kind {
+            "cpu" => {
+                let stats = CpuStats {
+                    cpu: data.get("cpu").and_then(Value::as_f64).unwrap_or(0.0),
+                    memory: data.get("memory").and_then(Value::as_u64).unwrap_or(0),
+                    time: Local::now().timestamp(),
+                };
+                MODEL.cpu.lock().unwrap().insert(username.clone(), stats);
+            }
+            "console" => update_console(username, data),
+            _ => {}
+        }
+    }
+}
+
+/// 连接 socket 并持续接收数据，连接正常关闭时返回 `Ok`
+/// 连接后发送 `auth <token>` 认证，认证成功后订阅所有频道
+async fn connect_once(url: &str, token: &str, subscriptions: &Subscriptions) -> Result<(), String> {
+    let (mut socket, _) = connect_async(url).await.map_err(|e| e.to_string())?;
+    socket
+        .send(Message::text(format!("auth {}", token)))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    while let Some(message) = socket.next().await {
+        let text = match message.map_err(|e| e.to_string())? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        // 较大的消息会压缩为 `gz:` 开头的 base64 数据
+        let text = match decode_gz(text.as_str()) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("Failed to decode socket message: {}", e);
+                continue;
+            }
+        };
+        if let Some(result) = text.strip_prefix("auth ") {
+            if !result.starts_with("ok") {
+                return Err("socket 认证失败".to_string());
+            }
+            reset_rooms(&subscriptions.rooms);
+            for channel in subscriptions.channels() {
+                socket
+                    .send(Message::text(format!("subscribe {}", channel)))
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+        } else {
+            handle_message(&text, subscriptions);
+        }
+    }
+    Ok(())
+}
+
+/// 查询需要订阅的玩家和房间
+async fn query_subscriptions(
+    api: &ScreepsApi,
+    usernames: &[String],
+) -> ScreepsResult<Subscriptions> {
+    let mut subscriptions = Subscriptions::default();
+    for username in usernames {
+        let user_id = query_user_id(api, username).await?;
+        for (shard, rooms) in query_user_rooms(api, &user_id).await? {
+            for room in rooms {
+                subscriptions.rooms.push((shard.clone(), room));
+            }
+        }
+        subscriptions.users.insert(user_id, username.clone());
+    }
+    Ok(subscriptions)
+}
+
+/// 保持 socket 连接，订阅 usernames 中玩家的房间、CPU 和控制台
+/// 连接断开后按 1、2、4... 秒（最多 60 秒）间隔重连，每次重连前重新查询房间列表
+async fn run_ingest(api: Arc<ScreepsApi>, url: String, token: String, usernames: Vec<String>) {
+    let mut delay = 1;
+    loop {
+        let start = Instant::now();
+        match query_subscriptions(&api, &usernames).await {
+            Ok(subscriptions) => {
+                if let Err(e) = connect_once(&url, &token, &subscriptions).await {
+                    eprintln!("Socket connection failed: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Failed to query socket subscriptions: {}", e),
+        }
+        // 连接保持了一段时间后断开时立即重置重连间隔
+        if start.elapsed() > Duration::from_secs(60) {
+            delay = 1;
+        }
+        tokio::time::sleep(Duration::from_secs(delay)).await;
+        delay = (delay * 2).min(60);
+    }
+}
+
+/// 使用玩家自己 token 的接入任务，键为小写的玩家名称
+static PLAYER_JOBS: LazyLock<Mutex<HashMap<String, JoinHandle<()>>>> =
+    LazyLock::new(Default::default);
+
+/// 启动 socket 数据接入任务，未开启时不启动
+/// 注册了 token 的玩家各自使用自己的 token 连接，`usernames` 中的玩家使用配置的 token 连接
+pub fn spawn_ingest_job(api: Arc<ScreepsApi>, config: Arc<Config>) -> Option<JoinHandle<()>> {
+    if !config.ingest.enabled {
+        return None;
+    }
+    let _ = MAX_AGE.set(Duration::from_secs(config.ingest.max_age));
+    for (username, token) in tokens::all_tokens() {
+        start_player_ingest(api.clone(), &config, username, token);
+    }
+
+    if config.ingest.usernames.is_empty() {
+        return None;
+    }
+    let Some(token) = config
+        .ingest
+        .token
+        .clone()
+        .or_else(|| std::env::var("SCREEPS_TOKEN").ok())
+    else {
+        eprintln!("Socket ingest is enabled but no token is configured");
+        return None;
+    };
+    Some(tokio::spawn(run_ingest(
+        api,
+        config.ingest.url.clone(),
+        token,
+        config.ingest.usernames.clone(),
+    )))
+}
+
+/// 使用玩家自己的 token 接入玩家的数据，替换该玩家已有的接入任务，未开启接入时不启动
+pub fn start_player_ingest(api: Arc<ScreepsApi>, config: &Config, username: String, token: String) {
+    if !config.ingest.enabled {
+        return;
+    }
+    let key = username.to_lowercase();
+    let job = tokio::spawn(run_ingest(
+        api,
+        config.ingest.url.clone(),
+        token,
+        vec![username],
+    ));
+    if let Some(old) = PLAYER_JOBS.lock().unwrap().insert(key, job) {
+        old.abort();
+    }
+}
+
+/// 停止使用玩家自己 token 的接入任务
+pub fn stop_player_ingest(username: &str) {
+    if let Some(job) = PLAYER_JOBS.lock().unwrap().remove(&username.to_lowercase()) {
+        job.abort();
+    }
+}
+
+/// 读取内存中的房间对象，未开启接入、没有该房间数据、数据已过期或有对象解析失败时返回 `None`
+pub fn room_objects(shard: &str, room: &str) -> Option<Vec<RoomObject>> {
+    let max_age = *MAX_AGE.get()?;
+    let rooms = MODEL.rooms.lock().unwrap();
+    let state = rooms.get(&(shard.to_string(), room.to_string()))?;
+    if state.updated.elapsed() > max_age {
+        return None;
+    }
+    state
+        .objects
+        .iter()
+        .map(|(id, object)| {
+            serde_json::from_value(object.clone()).map_err(|e| {
+                eprintln!(
+                    "Failed to parse object {} in {}/{}, falling back to HTTP: {}",
+                    id, shard, room, e
+                );
+            })
+        })
+        .collect::<Result<_, _>>()
+        .ok()
+}
+
+/// 获取 socket 推送的玩家最新 CPU 和内存
+pub fn latest_cpu(username: &str) -> Option<CpuStats> {
+    MODEL.cpu.lock().unwrap().get(username).copied()
+}
+
+/// 查询玩家的实时 CPU 和控制台消息，需要开启 socket 数据接入
+/// 控制台消息是玩家的私有数据，调用者需要提供玩家注册的 token
+pub fn query_user_live(username: &str, owner_token: Option<&str>) -> ScreepsResult<UserLive> {
+    if MAX_AGE.get().is_none() {
+        return Err(error(ErrorCode::Unavailable, "未开启 socket 数据接入"));
+    }
+    tokens::owner_token(username, owner_token)?;
+    Ok(UserLive {
+        cpu: latest_cpu(username),
+        console: MODEL
+            .console
+            .lock()
+            .unwrap()
+            .get(username)
+            .map(|lines| lines.iter().cloned().collect())
+            .unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use flate2::{Compression, write::ZlibEncoder};
+    use serde_json::json;
+    use std::io::Write;
+
+    #[test]
+    fn test_merge() {
+        let mut object = json!({ "type": "storage", "store": { "energy": 100, "H": 5 }, "x": 1 });
+        merge(
+            &mut object,
+            json!({ "store": { "energy": 200, "H": null, "O": 3 }, "x": 2 }),
+        );
+        assert_eq!(
+            object,
+            json!({ "type": "storage", "store": { "energy": 200, "O": 3 }, "x": 2 })
+        );
+    }
+
+    /// 本地的 socket 服务端，模拟 Screeps 服务器推送数据
+    #[tokio::test]
+    async fn test_connect_once() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let mut recv = async || {
+                let message = socket.next().await.unwrap().unwrap();
+                message.to_text().unwrap().to_string()
+            };
+            assert_eq!(recv().await, "auth token");
+            let channels = vec![recv().await, recv().await, recv().await];
+
+            // 首次推送完整数据，使用压缩格式
+            let full = json!(["room:shardtest/W1N1", { "objects": {
+                "a": { "type": "storage", "store": { "energy": 100 } },
+                "b": { "type": "source", "energy": 3000 }
+            }}]);
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(full.to_string().as_bytes()).unwrap();
+            let compressed = format!("gz:{}", STANDARD.encode(encoder.finish().unwrap()));
+            // 之后推送增量数据
+            let patch = json!(["room:shardtest/W1N1", { "objects": {
+                "a": { "store": { "energy": 200 } },
+                "b": null
+            }}]);
+            let cpu = json!(["user:u1/cpu", { "cpu": 12.5, "memory": 3000 }]);
+            let console = json!(["user:u1/console", {
+                "messages": { "log": ["hello"], "results": [] },
+                "shard": "shardtest"
+            }]);
+            for message in [
+                "time 1".to_string(),
+                "auth ok token".to_string(),
+                compressed,
+                patch.to_string(),
+                cpu.to_string(),
+                console.to_string(),
+            ] {
+                socket.send(Message::text(message)).await.unwrap();
+            }
+            socket.close(None).await.unwrap();
+            channels
+        });
+
+        // 上次连接留下的数据在重新订阅后清除
+        update_room(
+            "shardtest",
+            "W1N1",
+            &json!({ "objects": { "stale": { "type": "road" } } }),
+        );
+        let subscriptions = Subscriptions {
+            rooms: vec![("shardtest".to_string(), "W1N1".to_string())],
+            users: HashMap::from([("u1".to_string(), "tester".to_string())]),
+        };
+        connect_once(&format!("ws://{}", addr), "token", &subscriptions)
+            .await
+            .unwrap();
+
+        let channels = server.await.unwrap();
+        assert_eq!(
+            channels,
+            vec![
+                "subscribe room:shardtest/W1N1",
+                "subscribe user:u1/cpu",
+                "subscribe user:u1/console",
+            ]
+        );
+        {
+            let rooms = MODEL.rooms.lock().unwrap();
+            let state = &rooms[&("shardtest".to_string(), "W1N1".to_string())];
+            assert_eq!(state.objects.len(), 1);
+            assert_eq!(
+                state.objects["a"],
+                json!({ "type": "storage", "store": { "energy": 200 } })
+            );
+        }
+        assert_eq!(MODEL.cpu.lock().unwrap()["tester"].memory, 3000);
+        let console = MODEL.console.lock().unwrap();
+        assert_eq!(console["tester"].len(), 1);
+        assert_eq!(console["tester"][0].message, "hello");
+    }
+}
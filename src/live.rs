@@ -0,0 +1,228 @@
+use crate::{
+    error, ratelimit,
+    res::{ShardRes, query_res_cached},
+};
+use screeps_rust_api::ScreepsApi;
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::{broadcast, mpsc},
+    time::{Instant, Interval, MissedTickBehavior},
+};
+
+/// 一个玩家一个 shard 的最新资源
+pub struct ResUpdate {
+    pub username: String,
+    pub shard: String,
+    pub res: HashMap<String, i32>,
+}
+
+/// 资源更新广播，每次 `query_res` 查询到新数据时发送
+static UPDATES: LazyLock<broadcast::Sender<Arc<ResUpdate>>> =
+    LazyLock::new(|| broadcast::channel(256).0);
+
+/// 最多保存的资源快照数，超过时删除最久未更新的
+const SNAPSHOTS_SIZE: usize = 1024;
+
+/// 资源快照，键为 (玩家名称, shard)，值为 (更新时间, 资源)
+type Snapshots = HashMap<(String, String), (Instant, HashMap<String, i32>)>;
+
+/// 最新的资源快照
+static SNAPSHOTS: LazyLock<Mutex<Snapshots>> = LazyLock::new(Default::default);
+
+/// 保存一个资源快照，快照数达到上限时先删除最久未更新的
+fn insert_snapshot(snapshots: &mut Snapshots, key: (String, String), res: HashMap<String, i32>) {
+    if !snapshots.contains_key(&key)
+        && snapshots.len() >= SNAPSHOTS_SIZE
+        && let Some(oldest) = snapshots
+            .iter()
+            .min_by_key(|(_, (time, _))| *time)
+            .map(|(key, _)| key.clone())
+    {
+        snapshots.remove(&oldest);
+    }
+    snapshots.insert(key, (Instant::now(), res));
+}
+
+/// 发布玩家最新的资源，参数为 query_res 的结果
+pub fn publish(username: &str, res: &ShardRes) {
+    let mut snapshots = SNAPSHOTS.lock().unwrap();
+    for (shard, res) in res {
+        insert_snapshot(
+            &mut snapshots,
+            (username.to_string(), shard.clone()),
+            res.clone(),
+        );
+        // 没有订阅者时发送失败，直接忽略
+        let _ = UPDATES.send(Arc::new(ResUpdate {
+            username: username.to_string(),
+            shard: shard.clone(),
+            res: res.clone(),
+        }));
+    }
+}
+
+/// 订阅资源更新
+pub fn subscribe() -> broadcast::Receiver<Arc<ResUpdate>> {
+    UPDATES.subscribe()
+}
+
+/// 获取玩家最新的资源快照，返回 (shard, 资源)
+/// shard 为 `all` 时返回所有 shard 的快照
+pub fn snapshots(username: &str, shard: &str) -> Vec<(String, HashMap<String, i32>)> {
+    SNAPSHOTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|((u, s), _)| u == username && (shard == "all" || s == shard))
+        .map(|((_, s), (_, res))| (s.clone(), res.clone()))
+        .collect()
+}
+
+/// 订阅主题查询失败
+pub struct RefreshError {
+    pub username: String,
+    pub shard: String,
+    pub message: String,
+}
+
+/// 在后台查询一次玩家资源，查询结果通过广播推送，查询失败时把错误发送给订阅者
+/// 开启限流时查询的消耗计入订阅者的令牌桶，没有令牌时不查询
+pub fn request_refresh(
+    api: Arc<ScreepsApi>,
+    username: String,
+    shard: String,
+    client: Option<ratelimit::Client>,
+    errors: mpsc::UnboundedSender<RefreshError>,
+) {
+    tokio::spawn(async move {
+        let query = query_res_cached(&api, &username, &shard);
+        let result = match &client {
+            Some(client) => client.run(query).await,
+            None => Ok(query.await),
+        };
+        let message = match result {
+            Ok(Ok(_)) => return,
+            Ok(Err(e)) => error::classify(&e).1,
+            Err(retry_after) => format!("请求过于频繁，请 {} 秒后重试", retry_after),
+        };
+        // 订阅者已断开时发送失败，直接忽略
+        let _ = errors.send(RefreshError {
+            username,
+            shard,
+            message,
+        });
+    });
+}
+
+/// 订阅主题的轮询间隔，由环境变量 `LIVE_POLL_INTERVAL` 提供（秒），默认 60，为 0 时不轮询
+pub fn poll_interval() -> Duration {
+    let secs = std::env::var("LIVE_POLL_INTERVAL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(60);
+    Duration::from_secs(secs)
+}
+
+/// 订阅主题的轮询定时器，第一次在一个间隔之后触发，不轮询时为 None
+pub fn poll_timer() -> Option<Interval> {
+    let period = poll_interval();
+    if period.is_zero() {
+        return None;
+    }
+    let mut timer = tokio::time::interval_at(Instant::now() + period, period);
+    timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    Some(timer)
+}
+
+/// 等待下一次轮询，不轮询时一直等待
+pub async fn next_poll(timer: &mut Option<Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// 判断更新是否属于订阅的主题，订阅 `all` 时匹配所有 shard
+pub fn topic_matches(topic: &(String, String), update: &ResUpdate) -> bool {
+    topic.0 == update.username && (topic.1 == "all" || topic.1 == update.shard)
+}
+
+/// 计算资源变化，返回数量发生变化的资源及其新数量，消失的资源数量为 0
+/// 参数：
+/// - threshold: 变化量的绝对值大于该值才计入
+pub fn diff(
+    old: &HashMap<String, i32>,
+    new: &HashMap<String, i32>,
+    threshold: i32,
+) -> HashMap<String, i32> {
+    let mut changes = HashMap::new();
+    for (resource, &amount) in new {
+        let old_amount = old.get(resource).copied().unwrap_or(0);
+        if (amount - old_amount).abs() > threshold {
+            changes.insert(resource.clone(), amount);
+        }
+    }
+    for (resource, &old_amount) in old {
+        if !new.contains_key(resource) && old_amount.abs() > threshold {
+            changes.insert(resource.clone(), 0);
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_snapshot() {
+        let mut snapshots = Snapshots::new();
+        for i in 0..=SNAPSHOTS_SIZE {
+            insert_snapshot(
+                &mut snapshots,
+                (format!("player{}", i), "shard3".to_string()),
+                HashMap::new(),
+            );
+        }
+        assert_eq!(snapshots.len(), SNAPSHOTS_SIZE);
+        assert!(!snapshots.contains_key(&("player0".to_string(), "shard3".to_string())));
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = HashMap::from([
+            ("energy".to_string(), 1000),
+            ("H".to_string(), 500),
+            ("O".to_string(), 10),
+        ]);
+        let new = HashMap::from([
+            ("energy".to_string(), 1200),
+            ("O".to_string(), 15),
+            ("XGH2O".to_string(), 300),
+        ]);
+        assert_eq!(
+            diff(&old, &new, 0),
+            HashMap::from([
+                ("energy".to_string(), 1200),
+                ("H".to_string(), 0),
+                ("O".to_string(), 15),
+                ("XGH2O".to_string(), 300),
+            ])
+        );
+        assert!(diff(&new, &new, 0).is_empty());
+        assert_eq!(
+            diff(&old, &new, 100),
+            HashMap::from([
+                ("energy".to_string(), 1200),
+                ("H".to_string(), 0),
+                ("XGH2O".to_string(), 300),
+            ])
+        );
+    }
+}
@@ -1,37 +1,263 @@
 use axum::{
     Router,
-    body::Body,
-    extract::Query,
-    http::{StatusCode, header},
-    response::{IntoResponse, Json, Response},
-    routing::get,
+    extract::{Extension, Query, WebSocketUpgrade},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware,
+    response::{
+        IntoResponse, Json, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{get, post},
 };
+use futures::Stream;
 use screeps_rust_api::screeps_api_from_env;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use tokio_util::io::ReaderStream;
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc};
+use tower_http::{compression::CompressionLayer, set_header::SetResponseHeaderLayer};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
 
-use crate::res::draw_res_image;
+use crate::{defense::draw_defense_image, res::draw_res_image, user::draw_user_overview_image};
 
+mod alert;
+mod auth;
+mod config;
 mod constants;
+mod cpu;
+mod creep;
+mod cron;
+mod defense;
+mod error;
+mod export;
+mod headers;
+mod health;
+mod history;
+mod ingest;
+mod live;
+mod memory;
+mod metrics;
+mod openapi;
+mod plan;
+mod privacy;
+mod ratelimit;
+mod report;
 mod res;
+mod room;
+mod sse;
+mod stats;
+mod tokens;
+mod user;
 mod utils;
+mod v1;
+mod webhook;
+mod ws;
 
 // 定义查询参数结构体
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct ResQueryParams {
+    /// 玩家名称
     username: String,
+    /// shard 名称，`all` 表示所有 shard
     shard: String,
 }
 
+// 定义玩家查询参数结构体
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct UserQueryParams {
+    /// 玩家名称
+    username: String,
+}
+
+// 定义房间查询参数结构体，shard 默认为所有 shard
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RoomQueryParams {
+    /// 玩家名称
+    username: String,
+    /// shard 名称，默认为 `all`，表示所有 shard
+    #[serde(default = "default_shard")]
+    shard: String,
+}
+
+fn default_shard() -> String {
+    "all".to_string()
+}
+
+// 定义建筑查询参数结构体
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct StructureQueryParams {
+    /// 玩家名称
+    username: String,
+    /// shard 名称，默认为 `all`，表示所有 shard
+    #[serde(default = "default_shard")]
+    shard: String,
+    /// rampart 和 wall 血量低于该值视为受损
+    #[serde(default = "default_min_hits")]
+    min_hits: u32,
+}
+
+fn default_min_hits() -> u32 {
+    1_000_000
+}
+
+// 定义 creep 查询参数结构体
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CreepQueryParams {
+    /// 玩家名称
+    username: String,
+    /// shard 名称，默认为 `all`，表示所有 shard
+    #[serde(default = "default_shard")]
+    shard: String,
+    /// 是否按名称前缀（角色）分组
+    #[serde(default)]
+    group_by_prefix: bool,
+}
+
+// 定义资源事件流查询参数结构体
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct StreamQueryParams {
+    /// 玩家名称
+    username: String,
+    /// shard 名称，默认为 `all`，表示所有 shard
+    #[serde(default = "default_shard")]
+    shard: String,
+    /// 资源数量变化量超过该值才推送，默认为 0
+    #[serde(default)]
+    threshold: i32,
+}
+
+// 定义 CPU 历史查询参数结构体
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CpuQueryParams {
+    /// 玩家名称
+    username: String,
+    /// shard 名称
+    shard: String,
+    /// 起始时间，unix 时间戳（秒）
+    from: Option<i64>,
+    /// 结束时间，unix 时间戳（秒）
+    to: Option<i64>,
+}
+
+// 定义 Memory 查询参数结构体
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MemoryQueryParams {
+    /// 玩家名称
+    username: String,
+    /// shard 名称
+    shard: String,
+    /// Memory 路径，如 `stats.cpu`，默认为整个 Memory
+    #[serde(default)]
+    path: String,
+}
+
+// 定义资源图片查询参数结构体
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ResImageQueryParams {
+    /// 玩家名称
+    username: String,
+    /// shard 名称，`all` 表示所有 shard，绘制统计数据时不支持 `all`
+    shard: String,
+    /// 数据来源，`stats` 时绘制统计数据历史的折线图
+    #[serde(default)]
+    source: history::HistorySource,
+    /// 逗号分隔的统计数据字段，如 `cpu.used,gcl.level`，为空时绘制最新记录中的前几个字段
+    fields: Option<String>,
+    /// 起始时间，unix 时间戳（秒）
+    from: Option<i64>,
+    /// 结束时间，unix 时间戳（秒）
+    to: Option<i64>,
+}
+
+// 定义资源平衡查询参数结构体
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct BalanceQueryParams {
+    /// 玩家名称
+    username: String,
+    /// shard 名称，不支持 `all`
+    shard: String,
+}
+
+// 定义资源导出查询参数结构体
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExportQueryParams {
+    /// 玩家名称
+    username: String,
+    /// shard 名称，默认为 `all`，表示所有 shard
+    #[serde(default = "default_shard")]
+    shard: String,
+    /// 导出格式
+    #[serde(default)]
+    format: export::ExportFormat,
+    /// 为 true 时导出历史记录
+    #[serde(default)]
+    history: bool,
+    /// 历史记录的来源，统计数据只有历史记录，`stats` 时总是导出历史记录
+    #[serde(default)]
+    source: history::HistorySource,
+    /// 逗号分隔的资源或统计数据字段，只导出历史记录时有效，为空时导出所有
+    fields: Option<String>,
+    /// 历史记录起始时间，unix 时间戳（秒）
+    from: Option<i64>,
+    /// 历史记录结束时间，unix 时间戳（秒）
+    to: Option<i64>,
+}
+
 // 定义响应结构体
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct ResResponse {
     success: bool,
-    data: Option<std::collections::HashMap<String, std::collections::HashMap<String, i32>>>,
+    /// shard -> 资源类型 -> 数量
+    #[schema(value_type = Option<HashMap<String, HashMap<String, i32>>>)]
+    data: Option<res::ShardRes>,
+    error: Option<String>,
+}
+
+// 定义通用响应结构体，格式与 ResResponse 保持一致
+#[derive(Serialize, ToSchema)]
+struct ApiResponse<T> {
+    success: bool,
+    data: Option<T>,
     error: Option<String>,
 }
 
+// 将查询结果转换为 JSON 响应
+fn json_response<T: Serialize>(
+    result: screeps_rust_api::ScreepsResult<T>,
+) -> (StatusCode, Json<ApiResponse<T>>) {
+    match result {
+        Ok(data) => (
+            StatusCode::OK,
+            Json(ApiResponse {
+                success: true,
+                data: Some(data),
+                error: None,
+            }),
+        ),
+        Err(e) => {
+            let (code, message) = error::classify(&e);
+            (
+                code.map_or(StatusCode::INTERNAL_SERVER_ERROR, error::ErrorCode::status),
+                Json(ApiResponse {
+                    success: false,
+                    data: None,
+                    error: Some(message),
+                }),
+            )
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     utils::create_data_dir().expect("create data dir failed");
@@ -41,31 +267,251 @@ async fn main() {
 
     // 初始化API客户端
     let api = Arc::new(screeps_api_from_env!().unwrap());
+    // 读取配置文件
+    let config = Arc::new(config::load_config().expect("load config failed"));
+
+    // 启动后台任务
+    let report_log = report::ReportLog::default();
+    let mut jobs = Vec::new();
+    if let Some(handle) = alert::spawn_alert_job(api.clone(), config.clone()) {
+        jobs.push(("alerts", handle));
+    }
+    if let Some(handle) = report::spawn_report_job(api.clone(), config.clone(), report_log.clone())
+    {
+        jobs.push(("reports", handle));
+    }
+    if let Some(handle) = ingest::spawn_ingest_job(api.clone(), config.clone()) {
+        jobs.push(("ingest", handle));
+    }
+    if let Some(handle) = cpu::spawn_cpu_job(config.clone()) {
+        jobs.push(("cpu", handle));
+    }
+    if let Some(handle) = stats::spawn_stats_job(config.clone()) {
+        jobs.push(("stats", handle));
+    }
+    let jobs: health::Jobs = Arc::new(jobs);
 
     // 构建应用路由
-    let app = Router::new()
+    let mut app = Router::new()
         // `GET /` goes to `root`
         .route("/", get(root))
+        .route("/healthz", get(get_healthz_handler))
+        .route(
+            "/readyz",
+            get({
+                let api = api.clone();
+                move || get_readyz_handler(api.clone(), jobs.clone())
+            }),
+        )
+        .route(
+            "/version",
+            get({
+                let config = config.clone();
+                move || get_version_handler(config.clone())
+            }),
+        )
         .route(
             "/res",
             get({
                 let api = api.clone();
                 move |query: Query<ResQueryParams>| get_res_handler(api.clone(), query)
-            }),
+            })
+            .layer(middleware::from_fn_with_state(
+                config.clone(),
+                headers::etag_middleware,
+            )),
         )
         .route(
             "/res/image",
             get({
                 let api = api.clone();
-                move |query: Query<ResQueryParams>| get_res_image_handler(api.clone(), query)
+                move |query: Query<ResImageQueryParams>| get_res_image_handler(api.clone(), query)
+            })
+            .layer(middleware::from_fn_with_state(
+                config.clone(),
+                headers::etag_middleware,
+            )),
+        )
+        .route(
+            "/res/stream",
+            get({
+                let api = api.clone();
+                move |client: Option<Extension<ratelimit::Client>>,
+                      query: Query<StreamQueryParams>| {
+                    get_res_stream_handler(api.clone(), client, query)
+                }
+            }),
+        )
+        .route(
+            "/user/overview",
+            get({
+                let api = api.clone();
+                move |headers: HeaderMap, query: Query<UserQueryParams>| {
+                    get_user_overview_handler(api.clone(), headers, query)
+                }
+            }),
+        )
+        .route(
+            "/user/overview/image",
+            get({
+                let api = api.clone();
+                move |query: Query<UserQueryParams>| {
+                    get_user_overview_image_handler(api.clone(), query)
+                }
+            }),
+        )
+        .route("/user/live", get(get_user_live_handler))
+        .route("/user/memory", get(get_user_memory_handler))
+        .route("/user/market/orders", get(get_market_orders_handler))
+        .route("/user/cpu", get(get_cpu_handler))
+        .route("/user/cpu/image", get(get_cpu_image_handler))
+        .route(
+            "/rooms/controllers",
+            get({
+                let api = api.clone();
+                move |query: Query<RoomQueryParams>| get_controllers_handler(api.clone(), query)
+            }),
+        )
+        .route(
+            "/rooms/structures",
+            get({
+                let api = api.clone();
+                move |query: Query<StructureQueryParams>| get_structures_handler(api.clone(), query)
+            }),
+        )
+        .route(
+            "/rooms/defense",
+            get({
+                let api = api.clone();
+                move |query: Query<RoomQueryParams>| get_defense_handler(api.clone(), query)
+            }),
+        )
+        .route(
+            "/rooms/defense/image",
+            get({
+                let api = api.clone();
+                move |query: Query<RoomQueryParams>| get_defense_image_handler(api.clone(), query)
+            }),
+        )
+        .route(
+            "/rooms/creeps",
+            get({
+                let api = api.clone();
+                move |query: Query<CreepQueryParams>| get_creeps_handler(api.clone(), query)
+            }),
+        )
+        .route(
+            "/user/powercreeps",
+            get({
+                let api = api.clone();
+                move |headers: HeaderMap, query: Query<UserQueryParams>| {
+                    get_power_creeps_handler(api.clone(), headers, query)
+                }
+            }),
+        )
+        .route(
+            "/rooms/minerals",
+            get({
+                let api = api.clone();
+                move |query: Query<RoomQueryParams>| get_minerals_handler(api.clone(), query)
+            }),
+        )
+        .route(
+            "/plan/balance",
+            get({
+                let api = api.clone();
+                let config = config.clone();
+                move |query: Query<BalanceQueryParams>| {
+                    get_balance_plan_handler(api.clone(), config.clone(), query)
+                }
+            }),
+        )
+        .route(
+            "/reports/log",
+            get({
+                let report_log = report_log.clone();
+                move || get_report_log_handler(report_log.clone())
+            }),
+        )
+        .route(
+            "/privacy",
+            get(get_privacy_handler).post(post_privacy_handler),
+        )
+        .route(
+            "/tokens",
+            post({
+                let api = api.clone();
+                let config = config.clone();
+                move |body: Json<tokens::TokenRegistration>| {
+                    post_tokens_handler(api.clone(), config.clone(), body)
+                }
+            })
+            .delete(delete_tokens_handler),
+        )
+        .route("/metrics", get(get_metrics_handler))
+        .route(
+            "/ws",
+            get({
+                let api = api.clone();
+                let config = config.clone();
+                move |key: Option<Extension<auth::ApiKey>>,
+                      client: Option<Extension<ratelimit::Client>>,
+                      upgrade: WebSocketUpgrade| {
+                    get_ws_handler(api.clone(), config.clone(), key, client, upgrade)
+                }
             }),
-        );
+        )
+        .route(
+            "/res/export",
+            get({
+                let api = api.clone();
+                move |query: Query<ExportQueryParams>| get_res_export_handler(api.clone(), query)
+            }),
+        )
+        .nest(
+            "/api/v1",
+            v1::router(api.clone(), config.clone(), report_log.clone()),
+        )
+        .merge(SwaggerUi::new("/docs").url("/openapi.json", openapi::ApiDoc::openapi()))
+        .layer(middleware::from_fn_with_state(
+            config.clone(),
+            privacy::privacy_middleware,
+        ))
+        // 先认证再限流，限流时按认证得到的 API key 计算额度
+        .layer(middleware::from_fn_with_state(
+            config.clone(),
+            ratelimit::rate_limit_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            config.clone(),
+            auth::auth_middleware,
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::X_FRAME_OPTIONS,
+            HeaderValue::from_static("DENY"),
+        ));
+    if config.http.compression {
+        app = app.layer(CompressionLayer::new());
+    }
+    // 跨域放在最外层，预检请求不需要认证
+    if let Some(cors) = headers::cors_layer(&config.http) {
+        app = app.layer(cors);
+    }
 
     // 运行应用，监听3000端口
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{port}"))
         .await
         .unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
 
 // 基础处理函数，返回静态字符串
@@ -73,12 +519,67 @@ async fn root() -> &'static str {
     "Hello, World!"
 }
 
+// 存活检查，进程在运行即返回 ok
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses((status = 200, description = "进程存活", body = String)),
+    tag = "monitoring"
+)]
+async fn get_healthz_handler() -> &'static str {
+    "ok"
+}
+
+// 就绪检查，上游可访问、data 目录可写且后台任务在运行时返回 200，否则返回 503
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "服务就绪", body = health::Readiness),
+        (status = 503, description = "服务未就绪", body = health::Readiness)
+    ),
+    tag = "monitoring"
+)]
+async fn get_readyz_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    jobs: health::Jobs,
+) -> impl IntoResponse {
+    let readiness = health::check_readiness(&api, &jobs).await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+// 获取版本信息的处理函数
+#[utoipa::path(
+    get,
+    path = "/version",
+    responses((status = 200, description = "版本信息", body = health::VersionInfo)),
+    tag = "monitoring"
+)]
+async fn get_version_handler(config: Arc<config::Config>) -> Json<health::VersionInfo> {
+    Json(health::version_info(&config))
+}
+
 // 获取玩家资源信息的处理函数
+#[utoipa::path(
+    get,
+    path = "/res",
+    params(ResQueryParams),
+    responses(
+        (status = 200, description = "各 shard 的资源数量", body = ResResponse),
+        (status = 500, description = "查询失败", body = ResResponse)
+    ),
+    tag = "res"
+)]
 async fn get_res_handler(
     api: Arc<screeps_rust_api::ScreepsApi>,
     Query(params): Query<ResQueryParams>,
 ) -> (StatusCode, Json<ResResponse>) {
-    let result = res::query_res(&api, &params.username, &params.shard).await;
+    let result = res::query_res_cached(&api, &params.username, &params.shard).await;
 
     match result {
         Ok(data) => (
@@ -89,38 +590,592 @@ async fn get_res_handler(
                 error: None,
             }),
         ),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ResResponse {
-                success: false,
-                data: None,
-                error: Some(e.to_string()),
-            }),
-        ),
+        Err(e) => {
+            let (code, message) = error::classify(&e);
+            (
+                code.map_or(StatusCode::INTERNAL_SERVER_ERROR, error::ErrorCode::status),
+                Json(ResResponse {
+                    success: false,
+                    data: None,
+                    error: Some(message),
+                }),
+            )
+        }
     }
 }
 
 // 获取玩家资源信息图片的处理函数
+#[utoipa::path(
+    get,
+    path = "/res/image",
+    params(ResImageQueryParams),
+    responses(
+        (status = 200, description = "资源图片，`source=stats` 时为统计数据折线图", content_type = "image/png"),
+        (status = 404, description = "绘制失败", body = String)
+    ),
+    tag = "res"
+)]
 async fn get_res_image_handler(
     api: Arc<screeps_rust_api::ScreepsApi>,
-    Query(params): Query<ResQueryParams>,
+    Query(params): Query<ResImageQueryParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let not_found = |e: Box<dyn std::error::Error>| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Error: {}", error::parse(&e.to_string()).1),
+        )
+    };
+    match params.source {
+        history::HistorySource::Res => {
+            let png = draw_res_image(&api, &params.username, &params.shard)
+                .await
+                .map_err(not_found)?;
+            Ok(png_response(png))
+        }
+        history::HistorySource::Stats => {
+            let png = stats::draw_stats_image(
+                &params.username,
+                &params.shard,
+                &stats::parse_fields(params.fields.as_deref()),
+                params.from,
+                params.to,
+            )
+            .map_err(not_found)?;
+            Ok(png_response(png))
+        }
+    }
+}
+
+// 推送玩家资源变化事件的处理函数
+#[utoipa::path(
+    get,
+    path = "/res/stream",
+    params(StreamQueryParams),
+    responses(
+        (status = 200, description = "资源快照和变化事件", content_type = "text/event-stream")
+    ),
+    tag = "res"
+)]
+async fn get_res_stream_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    client: Option<Extension<ratelimit::Client>>,
+    Query(params): Query<StreamQueryParams>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    Sse::new(sse::res_stream(
+        api,
+        params.username,
+        params.shard,
+        params.threshold,
+        client.map(|Extension(client)| client),
+    ))
+    .keep_alive(KeepAlive::default())
+}
+
+// 获取玩家概览信息的处理函数
+#[utoipa::path(
+    get,
+    path = "/user/overview",
+    params(UserQueryParams),
+    responses(
+        (status = 200, description = "玩家概览", body = ApiResponse<user::UserOverview>),
+        (status = 500, description = "查询失败", body = ApiResponse<()>)
+    ),
+    tag = "user"
+)]
+async fn get_user_overview_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    headers: HeaderMap,
+    Query(params): Query<UserQueryParams>,
+) -> impl IntoResponse {
+    json_response(
+        user::query_user_overview(&api, &params.username, tokens::request_token(&headers)).await,
+    )
+}
+
+// 获取玩家概览卡片图片的处理函数
+#[utoipa::path(
+    get,
+    path = "/user/overview/image",
+    params(UserQueryParams),
+    responses(
+        (status = 200, description = "玩家概览卡片", content_type = "image/png"),
+        (status = 404, description = "绘制失败", body = String)
+    ),
+    tag = "user"
+)]
+async fn get_user_overview_image_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    Query(params): Query<UserQueryParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let png = draw_user_overview_image(&api, &params.username)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Error: {}", error::parse(&e.to_string()).1),
+            )
+        })?;
+    Ok(png_response(png))
+}
+
+// 将在内存中绘制的 PNG 图片转换为响应
+fn png_response(png: Vec<u8>) -> Response {
+    ([(header::CONTENT_TYPE, "image/png")], png).into_response()
+}
+
+// 获取玩家实时 CPU 和控制台消息的处理函数
+#[utoipa::path(
+    get,
+    path = "/user/live",
+    params(UserQueryParams, ("X-Screeps-Token" = String, Header, description = "玩家注册的 Screeps token")),
+    responses(
+        (status = 200, description = "玩家实时状态", body = ApiResponse<ingest::UserLive>),
+        (status = 401, description = "未提供玩家 token", body = ApiResponse<()>),
+        (status = 403, description = "玩家未注册 token 或 token 不一致", body = ApiResponse<()>),
+        (status = 503, description = "未开启 socket 数据接入", body = ApiResponse<()>)
+    ),
+    tag = "user"
+)]
+async fn get_user_live_handler(
+    headers: HeaderMap,
+    Query(params): Query<UserQueryParams>,
+) -> impl IntoResponse {
+    json_response(ingest::query_user_live(
+        &params.username,
+        tokens::request_token(&headers),
+    ))
+}
+
+// 读取玩家 Memory 的处理函数
+#[utoipa::path(
+    get,
+    path = "/user/memory",
+    params(MemoryQueryParams, ("X-Screeps-Token" = String, Header, description = "玩家注册的 Screeps token")),
+    responses(
+        (status = 200, description = "Memory 中指定路径的数据", body = ApiResponse<serde_json::Value>),
+        (status = 401, description = "未提供玩家 token", body = ApiResponse<()>),
+        (status = 403, description = "玩家未注册 token 或 token 不一致", body = ApiResponse<()>),
+        (status = 500, description = "查询失败", body = ApiResponse<()>)
+    ),
+    tag = "user"
+)]
+async fn get_user_memory_handler(
+    headers: HeaderMap,
+    Query(params): Query<MemoryQueryParams>,
+) -> impl IntoResponse {
+    json_response(
+        memory::query_player_memory(
+            &params.username,
+            &params.path,
+            &params.shard,
+            tokens::request_token(&headers),
+        )
+        .await,
+    )
+}
+
+// 查询玩家市场订单的处理函数
+#[utoipa::path(
+    get,
+    path = "/user/market/orders",
+    params(UserQueryParams, ("X-Screeps-Token" = String, Header, description = "玩家注册的 Screeps token")),
+    responses(
+        (status = 200, description = "玩家的市场订单", body = ApiResponse<Vec<serde_json::Value>>),
+        (status = 401, description = "未提供玩家 token", body = ApiResponse<()>),
+        (status = 403, description = "玩家未注册 token 或 token 不一致", body = ApiResponse<()>),
+        (status = 500, description = "查询失败", body = ApiResponse<()>)
+    ),
+    tag = "user"
+)]
+async fn get_market_orders_handler(
+    headers: HeaderMap,
+    Query(params): Query<UserQueryParams>,
 ) -> impl IntoResponse {
-    let path = draw_res_image(&api, &params.username, &params.shard)
+    json_response(
+        user::query_market_orders(&params.username, tokens::request_token(&headers)).await,
+    )
+}
+
+// 获取玩家 CPU 和内存历史的处理函数
+#[utoipa::path(
+    get,
+    path = "/user/cpu",
+    params(CpuQueryParams),
+    responses(
+        (status = 200, description = "CPU 和内存历史", body = ApiResponse<Vec<history::HistoryRecord<cpu::CpuRecord>>>),
+        (status = 500, description = "查询失败", body = ApiResponse<()>)
+    ),
+    tag = "user"
+)]
+async fn get_cpu_handler(Query(params): Query<CpuQueryParams>) -> impl IntoResponse {
+    json_response(cpu::query_cpu(
+        &params.username,
+        &params.shard,
+        params.from,
+        params.to,
+    ))
+}
+
+// 获取玩家 CPU 和内存折线图的处理函数
+#[utoipa::path(
+    get,
+    path = "/user/cpu/image",
+    params(CpuQueryParams),
+    responses(
+        (status = 200, description = "CPU 和内存折线图", content_type = "image/png"),
+        (status = 404, description = "绘制失败", body = String)
+    ),
+    tag = "user"
+)]
+async fn get_cpu_image_handler(
+    Query(params): Query<CpuQueryParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let png = cpu::draw_cpu_image(&params.username, &params.shard, params.from, params.to)
+        .map_err(|e| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Error: {}", error::parse(&e.to_string()).1),
+            )
+        })?;
+    Ok(png_response(png))
+}
+
+// 获取玩家房间控制器信息的处理函数
+#[utoipa::path(
+    get,
+    path = "/rooms/controllers",
+    params(RoomQueryParams),
+    responses(
+        (status = 200, description = "房间控制器信息", body = ApiResponse<Vec<room::ControllerInfo>>),
+        (status = 500, description = "查询失败", body = ApiResponse<()>)
+    ),
+    tag = "rooms"
+)]
+async fn get_controllers_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    Query(params): Query<RoomQueryParams>,
+) -> impl IntoResponse {
+    json_response(room::query_controllers(&api, &params.username, &params.shard).await)
+}
+
+// 获取玩家房间建筑统计的处理函数
+#[utoipa::path(
+    get,
+    path = "/rooms/structures",
+    params(StructureQueryParams),
+    responses(
+        (status = 200, description = "房间建筑统计", body = ApiResponse<Vec<room::RoomStructures>>),
+        (status = 500, description = "查询失败", body = ApiResponse<()>)
+    ),
+    tag = "rooms"
+)]
+async fn get_structures_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    Query(params): Query<StructureQueryParams>,
+) -> impl IntoResponse {
+    json_response(
+        room::query_structures(&api, &params.username, &params.shard, params.min_hits).await,
+    )
+}
+
+// 获取玩家房间防御状态的处理函数
+#[utoipa::path(
+    get,
+    path = "/rooms/defense",
+    params(RoomQueryParams),
+    responses(
+        (status = 200, description = "房间防御状态", body = ApiResponse<Vec<defense::RoomDefense>>),
+        (status = 500, description = "查询失败", body = ApiResponse<()>)
+    ),
+    tag = "rooms"
+)]
+async fn get_defense_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    Query(params): Query<RoomQueryParams>,
+) -> impl IntoResponse {
+    json_response(defense::query_defense(&api, &params.username, &params.shard).await)
+}
+
+// 获取玩家房间防御状态图片的处理函数
+#[utoipa::path(
+    get,
+    path = "/rooms/defense/image",
+    params(RoomQueryParams),
+    responses(
+        (status = 200, description = "房间防御状态图片", content_type = "image/png"),
+        (status = 404, description = "绘制失败", body = String)
+    ),
+    tag = "rooms"
+)]
+async fn get_defense_image_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    Query(params): Query<RoomQueryParams>,
+) -> Result<Response, (StatusCode, String)> {
+    let png = draw_defense_image(&api, &params.username, &params.shard)
         .await
-        .map_err(|e| (StatusCode::NOT_FOUND, format!("Error: {}", e)))?;
+        .map_err(|e| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Error: {}", error::parse(&e.to_string()).1),
+            )
+        })?;
+    Ok(png_response(png))
+}
+
+// 获取玩家房间 creep 统计的处理函数
+#[utoipa::path(
+    get,
+    path = "/rooms/creeps",
+    params(CreepQueryParams),
+    responses(
+        (status = 200, description = "房间 creep 统计", body = ApiResponse<Vec<creep::RoomCreeps>>),
+        (status = 500, description = "查询失败", body = ApiResponse<()>)
+    ),
+    tag = "rooms"
+)]
+async fn get_creeps_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    Query(params): Query<CreepQueryParams>,
+) -> impl IntoResponse {
+    json_response(
+        creep::query_creeps(
+            &api,
+            &params.username,
+            &params.shard,
+            params.group_by_prefix,
+        )
+        .await,
+    )
+}
+
+// 获取玩家超能 creep 状态的处理函数
+#[utoipa::path(
+    get,
+    path = "/user/powercreeps",
+    params(UserQueryParams, ("X-Screeps-Token" = String, Header, description = "玩家注册的 Screeps token，提供时返回占用和剩余的 GPL 等级")),
+    responses(
+        (status = 200, description = "超能 creep 状态", body = ApiResponse<user::PowerCreepsOverview>),
+        (status = 500, description = "查询失败", body = ApiResponse<()>)
+    ),
+    tag = "user"
+)]
+async fn get_power_creeps_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    headers: HeaderMap,
+    Query(params): Query<UserQueryParams>,
+) -> impl IntoResponse {
+    json_response(
+        user::query_power_creeps(&api, &params.username, tokens::request_token(&headers)).await,
+    )
+}
 
-    let file = match tokio::fs::File::open(path).await {
-        Ok(file) => file,
-        Err(err) => return Err((StatusCode::NOT_FOUND, format!("File not found: {}", err))),
+// 获取玩家房间矿物信息的处理函数
+#[utoipa::path(
+    get,
+    path = "/rooms/minerals",
+    params(RoomQueryParams),
+    responses(
+        (status = 200, description = "房间矿物信息", body = ApiResponse<room::MineralsOverview>),
+        (status = 500, description = "查询失败", body = ApiResponse<()>)
+    ),
+    tag = "rooms"
+)]
+async fn get_minerals_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    Query(params): Query<RoomQueryParams>,
+) -> impl IntoResponse {
+    json_response(room::query_minerals(&api, &params.username, &params.shard).await)
+}
+
+// 获取资源平衡计划的处理函数
+#[utoipa::path(
+    get,
+    path = "/plan/balance",
+    params(BalanceQueryParams),
+    responses(
+        (status = 200, description = "资源平衡计划", body = ApiResponse<plan::BalancePlan>),
+        (status = 500, description = "查询失败", body = ApiResponse<()>)
+    ),
+    tag = "plan"
+)]
+async fn get_balance_plan_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    config: Arc<config::Config>,
+    Query(params): Query<BalanceQueryParams>,
+) -> impl IntoResponse {
+    json_response(
+        plan::query_balance_plan(&api, &params.username, &params.shard, &config.balance).await,
+    )
+}
+
+// 查询玩家数据公开设置的处理函数
+#[utoipa::path(
+    get,
+    path = "/privacy",
+    params(UserQueryParams),
+    responses(
+        (status = 200, description = "玩家的数据公开设置", body = ApiResponse<privacy::PlayerPrivacy>)
+    ),
+    tag = "user"
+)]
+async fn get_privacy_handler(Query(params): Query<UserQueryParams>) -> impl IntoResponse {
+    json_response(Ok(privacy::query_privacy(&params.username)))
+}
+
+// 玩家注册并设置数据公开范围的处理函数
+#[utoipa::path(
+    post,
+    path = "/privacy",
+    request_body = privacy::PrivacyRegistration,
+    responses(
+        (status = 200, description = "注册成功，返回玩家的数据公开设置", body = ApiResponse<privacy::PlayerPrivacy>),
+        (status = 500, description = "token 无效或保存失败", body = ApiResponse<()>)
+    ),
+    tag = "user"
+)]
+async fn post_privacy_handler(
+    Json(registration): Json<privacy::PrivacyRegistration>,
+) -> impl IntoResponse {
+    json_response(privacy::register(registration).await)
+}
+
+// 玩家注册自己 token 的处理函数
+#[utoipa::path(
+    post,
+    path = "/tokens",
+    request_body = tokens::TokenRegistration,
+    responses(
+        (status = 200, description = "注册成功", body = ApiResponse<tokens::TokenInfo>),
+        (status = 500, description = "token 无效或保存失败", body = ApiResponse<()>)
+    ),
+    tag = "user"
+)]
+async fn post_tokens_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    config: Arc<config::Config>,
+    Json(registration): Json<tokens::TokenRegistration>,
+) -> impl IntoResponse {
+    json_response(tokens::register(api, &config, registration.token).await)
+}
+
+// 玩家删除自己 token 的处理函数
+#[utoipa::path(
+    delete,
+    path = "/tokens",
+    request_body = tokens::TokenRegistration,
+    responses(
+        (status = 200, description = "删除成功", body = ApiResponse<tokens::TokenInfo>),
+        (status = 500, description = "token 无效或未注册", body = ApiResponse<()>)
+    ),
+    tag = "user"
+)]
+async fn delete_tokens_handler(
+    Json(registration): Json<tokens::TokenRegistration>,
+) -> impl IntoResponse {
+    json_response(tokens::unregister(registration.token).await)
+}
+
+// 获取定时报告发送记录的处理函数
+#[utoipa::path(
+    get,
+    path = "/reports/log",
+    responses(
+        (status = 200, description = "最近的报告发送记录", body = ApiResponse<Vec<report::ReportLogEntry>>)
+    ),
+    tag = "reports"
+)]
+async fn get_report_log_handler(log: report::ReportLog) -> impl IntoResponse {
+    let entries: Vec<_> = log.lock().unwrap().iter().cloned().collect();
+    json_response(Ok(entries))
+}
+
+// 输出 Prometheus 指标的处理函数
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Prometheus 文本格式指标", content_type = "text/plain", body = String)
+    ),
+    tag = "monitoring"
+)]
+// 开启认证时需要 API key，只输出 key 允许查询的玩家和 shard 的资源数量
+async fn get_metrics_handler(key: Option<Extension<auth::ApiKey>>) -> impl IntoResponse {
+    let visible = |username: &str, shard: &str| {
+        key.as_ref()
+            .is_none_or(|Extension(key)| key.allows(username, shard))
     };
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::METRICS.render(visible),
+    )
+}
 
-    let stream = ReaderStream::new(file);
+// 建立 WebSocket 连接，实时推送订阅的玩家资源
+async fn get_ws_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    config: Arc<config::Config>,
+    key: Option<Extension<auth::ApiKey>>,
+    client: Option<Extension<ratelimit::Client>>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let key = key.map(|Extension(key)| key);
+    let client = client.map(|Extension(client)| client);
+    upgrade.on_upgrade(move |socket| ws::handle_socket(api, config, key, client, socket))
+}
 
-    // 构建响应
-    let response = Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "image/jpeg")
-        .body(Body::from_stream(stream))
-        .unwrap();
-    Ok(response)
+// 导出玩家资源的处理函数
+#[utoipa::path(
+    get,
+    path = "/res/export",
+    params(ExportQueryParams),
+    responses(
+        (status = 200, description = "导出的文件", content(
+            (String = "text/csv"),
+            (String = "application/x-ndjson"),
+            (Vec<u8> = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")
+        )),
+        (status = 500, description = "导出失败", body = ApiResponse<()>)
+    ),
+    tag = "res"
+)]
+async fn get_res_export_handler(
+    api: Arc<screeps_rust_api::ScreepsApi>,
+    Query(params): Query<ExportQueryParams>,
+) -> Response {
+    match export_file(&api, &params).await {
+        Ok(file) => file_response(file),
+        Err(e) => json_response::<()>(Err(e)).into_response(),
+    }
+}
+
+// 按查询参数导出当前资源、资源历史或统计数据历史
+async fn export_file(
+    api: &screeps_rust_api::ScreepsApi,
+    params: &ExportQueryParams,
+) -> screeps_rust_api::ScreepsResult<export::ExportFile> {
+    if params.history || params.source == history::HistorySource::Stats {
+        export::export_history(
+            params.source,
+            &params.username,
+            &params.shard,
+            &stats::parse_fields(params.fields.as_deref()),
+            params.from,
+            params.to,
+            params.format,
+        )
+    } else {
+        export::export_res(api, &params.username, &params.shard, params.format).await
+    }
+}
+
+// 将导出的文件转换为下载响应
+fn file_response(file: export::ExportFile) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, file.content_type.to_string()),
+            (header::CONTENT_DISPOSITION, file.content_disposition()),
+        ],
+        file.body,
+    )
+        .into_response()
 }
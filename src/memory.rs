@@ -0,0 +1,228 @@
+use crate::{
+    config::StatsTarget,
+    error::{ErrorCode, error},
+    metrics::track_upstream,
+    tokens,
+    utils::decode_gz,
+};
+use screeps_rust_api::{ScreepsError, ScreepsResult};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use tokio::sync::OnceCell;
+
+/// Screeps HTTP API 地址，由环境变量 `SCREEPS_BASE_URL` 控制，默认为官服
+pub fn base_url() -> String {
+    std::env::var("SCREEPS_BASE_URL").unwrap_or("https://screeps.com".to_string())
+}
+
+/// 默认使用的 token，由环境变量 `SCREEPS_TOKEN` 提供
+pub fn token() -> ScreepsResult<String> {
+    std::env::var("SCREEPS_TOKEN")
+        .map_err(|_| error(ErrorCode::Unavailable, "未配置 SCREEPS_TOKEN"))
+}
+
+/// 使用 token 请求需要认证的 Screeps HTTP API，返回 `ok` 为 1 的响应
+/// 参数：
+/// - endpoint: `/api/` 之后的路径，如 `user/memory`
+pub async fn request_api(
+    client: &reqwest::Client,
+    token: &str,
+    endpoint: &str,
+    query: &[(&str, &str)],
+) -> ScreepsResult<Value> {
+    let request_error =
+        |e: reqwest::Error| ScreepsError::Api(format!("请求 {} 失败: {}", endpoint, e));
+    let response = client
+        .get(format!("{}/api/{}", base_url(), endpoint))
+        .header("X-Token", token)
+        .header("X-Username", token)
+        .query(query)
+        .send()
+        .await
+        .map_err(request_error)?;
+    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        return Err(error(ErrorCode::Unauthorized, "token 无效"));
+    }
+    let response: Value = response.json().await.map_err(request_error)?;
+    if response["ok"].as_i64() != Some(1) {
+        return Err(ScreepsError::Api(
+            response["error"]
+                .as_str()
+                .map(|e| e.to_string())
+                .unwrap_or(format!("请求 {} 失败", endpoint)),
+        ));
+    }
+    Ok(response)
+}
+
+/// 请求 Memory 接口，返回响应中的 `data`
+async fn request_memory(
+    client: &reqwest::Client,
+    token: &str,
+    endpoint: &str,
+    query: &[(&str, &str)],
+) -> ScreepsResult<Value> {
+    let mut response = request_api(client, token, &format!("user/{}", endpoint), query).await?;
+    Ok(response["data"].take())
+}
+
+/// 读取 token 所属玩家 Memory 中指定路径的数据，如 `stats`
+pub async fn query_memory(
+    client: &reqwest::Client,
+    token: &str,
+    path: &str,
+    shard: &str,
+) -> ScreepsResult<Value> {
+    let data = track_upstream(
+        "memory",
+        request_memory(client, token, "memory", &[("path", path), ("shard", shard)]),
+    )
+    .await?;
+    match data {
+        // 数据为 `gz:` 开头的压缩 JSON
+        Value::String(text) => {
+            let text = decode_gz(&text).map_err(ScreepsError::Api)?;
+            serde_json::from_str(&text)
+                .map_err(|e| ScreepsError::Api(format!("Memory 数据格式错误: {}", e)))
+        }
+        value => Ok(value),
+    }
+}
+
+/// 读取 token 所属玩家指定 shard 的内存段，按 JSON 解析，内存段为空时返回 null
+pub async fn query_memory_segment(
+    client: &reqwest::Client,
+    token: &str,
+    segment: u32,
+    shard: &str,
+) -> ScreepsResult<Value> {
+    let segment = segment.to_string();
+    let data = track_upstream(
+        "memory_segment",
+        request_memory(
+            client,
+            token,
+            "memory-segment",
+            &[("segment", &segment), ("shard", shard)],
+        ),
+    )
+    .await?;
+    match data {
+        Value::String(text) if text.is_empty() => Ok(Value::Null),
+        Value::String(text) => serde_json::from_str(&text)
+            .map_err(|e| ScreepsError::Api(format!("内存段数据格式错误: {}", e))),
+        value => Ok(value),
+    }
+}
+
+/// `SCREEPS_TOKEN` 所属的玩家，第一次使用时查询
+static TOKEN_USER: OnceCell<String> = OnceCell::const_new();
+
+/// 读取采集目标的统计数据，配置了内存段时读取内存段，否则读取 Memory 路径
+/// 玩家注册了自己的 token 时使用玩家的 token，否则使用 `SCREEPS_TOKEN`，
+/// `SCREEPS_TOKEN` 不属于该玩家时返回错误，避免读取到其他玩家的数据
+pub async fn query_stats(client: &reqwest::Client, target: &StatsTarget) -> ScreepsResult<Value> {
+    let token = match tokens::player_token(&target.username) {
+        Some(token) => token,
+        None => {
+            let token = token()?;
+            let owner = TOKEN_USER
+                .get_or_try_init(|| async {
+                    tokens::query_token_user(client, &token)
+                        .await
+                        .map(|user| user.username)
+                })
+                .await?;
+            if !owner.eq_ignore_ascii_case(&target.username) {
+                return Err(error(
+                    ErrorCode::Forbidden,
+                    format!(
+                        "玩家 {} 未注册 token，SCREEPS_TOKEN 属于玩家 {}",
+                        target.username, owner
+                    ),
+                ));
+            }
+            token
+        }
+    };
+    match target.segment {
+        Some(segment) => query_memory_segment(client, &token, segment, &target.shard).await,
+        None => query_memory(client, &token, &target.path, &target.shard).await,
+    }
+}
+
+/// 使用玩家自己的 token 读取玩家 Memory 中指定路径的数据
+/// 参数：
+/// - owner_token: 调用者提供的玩家 token，与玩家注册的 token 相同时才能读取
+pub async fn query_player_memory(
+    username: &str,
+    path: &str,
+    shard: &str,
+    owner_token: Option<&str>,
+) -> ScreepsResult<Value> {
+    let token = tokens::owner_token(username, owner_token)?;
+    query_memory(&reqwest::Client::new(), &token, path, shard).await
+}
+
+/// 将 JSON 中的数值字段展开为 `a.b.c` 形式的键，数组使用下标作为键
+pub fn flatten_numbers(value: &Value) -> BTreeMap<String, f64> {
+    fn walk(value: &Value, prefix: &str, result: &mut BTreeMap<String, f64>) {
+        let key = |name: &str| {
+            if prefix.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}.{}", prefix, name)
+            }
+        };
+        match value {
+            Value::Number(n) => {
+                if let Some(n) = n.as_f64() {
+                    result.insert(prefix.to_string(), n);
+                }
+            }
+            Value::Object(object) => {
+                for (name, value) in object {
+                    walk(value, &key(name), result);
+                }
+            }
+            Value::Array(array) => {
+                for (i, value) in array.iter().enumerate() {
+                    walk(value, &key(&i.to_string()), result);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut result = BTreeMap::new();
+    walk(value, "", &mut result);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_flatten_numbers() {
+        let stats = json!({
+            "cpu": { "used": 12.5, "bucket": 10000 },
+            "rooms": [{ "rcl": 8 }, { "rcl": 6, "name": "W1N1" }],
+            "ok": true
+        });
+        assert_eq!(
+            flatten_numbers(&stats),
+            BTreeMap::from([
+                ("cpu.bucket".to_string(), 10000.0),
+                ("cpu.used".to_string(), 12.5),
+                ("rooms.0.rcl".to_string(), 8.0),
+                ("rooms.1.rcl".to_string(), 6.0),
+            ])
+        );
+        assert_eq!(
+            flatten_numbers(&json!(3)),
+            BTreeMap::from([(String::new(), 3.0)])
+        );
+    }
+}
@@ -0,0 +1,388 @@
+use screeps_rust_api::ScreepsResult;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    future::Future,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// 最多保存资源数量的 (玩家, shard) 数，超过时删除最久未更新的
+const RESOURCE_SERIES_SIZE: usize = 1024;
+
+/// 耗时直方图的桶，单位秒
+const DURATION_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// 耗时直方图
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; DURATION_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, le) in self.buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= le {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        for (bucket, le) in self.buckets.iter().zip(DURATION_BUCKETS) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, le, bucket
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, self.count
+        );
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+/// 服务指标
+#[derive(Default)]
+pub struct Metrics {
+    /// 最近一次查询到的资源和更新时间，键为 (玩家, shard)
+    resources: Mutex<BTreeMap<(String, String), (Instant, HashMap<String, i32>)>>,
+    /// 上游请求耗时，键为接口名
+    upstream_duration: Mutex<BTreeMap<String, Histogram>>,
+    /// 上游请求错误次数，键为接口名
+    upstream_errors: Mutex<BTreeMap<String, u64>>,
+    /// 图片绘制耗时，键为图片类型
+    render_duration: Mutex<BTreeMap<String, Histogram>>,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+}
+
+/// 全局指标
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+/// 单次请求中的上游请求和缓存使用统计
+#[derive(Default, Clone, Copy)]
+pub struct RequestStats {
+    /// 上游请求次数
+    pub upstream_requests: u32,
+    /// 上游请求耗时之和，并发请求的耗时会累加
+    pub upstream_duration: Duration,
+    /// 使用了缓存数据时，最旧的缓存数据已存在的时间
+    pub cache_age: Option<Duration>,
+}
+
+tokio::task_local! {
+    static REQUEST_STATS: RefCell<RequestStats>;
+}
+
+/// 转义 Prometheus 标签值
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    /// 记录玩家最新的资源数据，参数为 query_res 的结果
+    pub fn record_resources(&self, username: &str, res: &HashMap<String, HashMap<String, i32>>) {
+        let mut resources = self.resources.lock().unwrap();
+        for (shard, res) in res {
+            let key = (username.to_string(), shard.clone());
+            if !resources.contains_key(&key)
+                && resources.len() >= RESOURCE_SERIES_SIZE
+                && let Some(oldest) = resources
+                    .iter()
+                    .min_by_key(|(_, (time, _))| *time)
+                    .map(|(key, _)| key.clone())
+            {
+                resources.remove(&oldest);
+            }
+            resources.insert(key, (Instant::now(), res.clone()));
+        }
+    }
+
+    /// 删除玩家所有 shard 的资源数量，玩家名称不区分大小写
+    pub fn remove_resources(&self, username: &str) {
+        self.resources
+            .lock()
+            .unwrap()
+            .retain(|(name, _), _| !name.eq_ignore_ascii_case(username));
+    }
+
+    /// 记录一次上游请求
+    pub fn record_upstream(&self, endpoint: &str, seconds: f64, success: bool) {
+        self.upstream_duration
+            .lock()
+            .unwrap()
+            .entry(endpoint.to_string())
+            .or_default()
+            .observe(seconds);
+        if !success {
+            *self
+                .upstream_errors
+                .lock()
+                .unwrap()
+                .entry(endpoint.to_string())
+                .or_insert(0) += 1;
+        }
+    }
+
+    /// 记录一次返回了错误结果的上游请求，请求本身成功但响应的 `ok` 不为 1
+    pub fn record_upstream_failure(&self, endpoint: &str) {
+        *self
+            .upstream_errors
+            .lock()
+            .unwrap()
+            .entry(endpoint.to_string())
+            .or_insert(0) += 1;
+    }
+
+    /// 记录一次图片绘制
+    pub fn record_render(&self, image: &str, seconds: f64) {
+        self.render_duration
+            .lock()
+            .unwrap()
+            .entry(image.to_string())
+            .or_default()
+            .observe(seconds);
+    }
+
+    /// 记录一次缓存查询
+    pub fn record_cache(&self, hit: bool) {
+        if hit {
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.cache_misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 输出 Prometheus 文本格式
+    /// 参数：
+    /// - visible: 是否输出该玩家和 shard 的资源数量，用于按 API key 的范围过滤
+    pub fn render(&self, visible: impl Fn(&str, &str) -> bool) -> String {
+        let mut out = String::new();
+
+        out.push_str(
+            "# HELP screeps_resource_amount Resource amount in storage, terminal and factory.\n",
+        );
+        out.push_str("# TYPE screeps_resource_amount gauge\n");
+        for ((username, shard), (_, res)) in self.resources.lock().unwrap().iter() {
+            if !visible(username, shard) {
+                continue;
+            }
+            let mut res: Vec<_> = res.iter().collect();
+            res.sort();
+            for (resource, amount) in res {
+                let _ = writeln!(
+                    out,
+                    "screeps_resource_amount{{username=\"{}\",shard=\"{}\",resource=\"{}\"}} {}",
+                    escape_label(username),
+                    escape_label(shard),
+                    escape_label(resource),
+                    amount
+                );
+            }
+        }
+
+        out.push_str(
+            "# HELP screeps_upstream_request_duration_seconds Screeps API request latency.\n",
+        );
+        out.push_str("# TYPE screeps_upstream_request_duration_seconds histogram\n");
+        for (endpoint, histogram) in self.upstream_duration.lock().unwrap().iter() {
+            histogram.render(
+                &mut out,
+                "screeps_upstream_request_duration_seconds",
+                &format!("endpoint=\"{}\"", escape_label(endpoint)),
+            );
+        }
+
+        out.push_str("# HELP screeps_upstream_request_errors_total Failed Screeps API requests.\n");
+        out.push_str("# TYPE screeps_upstream_request_errors_total counter\n");
+        for (endpoint, errors) in self.upstream_errors.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "screeps_upstream_request_errors_total{{endpoint=\"{}\"}} {}",
+                escape_label(endpoint),
+                errors
+            );
+        }
+
+        out.push_str("# HELP screeps_res_cache_requests_total Resource cache lookups.\n");
+        out.push_str("# TYPE screeps_res_cache_requests_total counter\n");
+        let _ = writeln!(
+            out,
+            "screeps_res_cache_requests_total{{result=\"hit\"}} {}",
+            self.cache_hits.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "screeps_res_cache_requests_total{{result=\"miss\"}} {}",
+            self.cache_misses.load(Ordering::Relaxed)
+        );
+
+        out.push_str("# HELP screeps_render_duration_seconds Image rendering duration.\n");
+        out.push_str("# TYPE screeps_render_duration_seconds histogram\n");
+        for (image, histogram) in self.render_duration.lock().unwrap().iter() {
+            histogram.render(
+                &mut out,
+                "screeps_render_duration_seconds",
+                &format!("image=\"{}\"", escape_label(image)),
+            );
+        }
+
+        out
+    }
+}
+
+/// 执行上游请求并记录耗时和错误
+pub async fn track_upstream<T>(
+    endpoint: &str,
+    request: impl Future<Output = ScreepsResult<T>>,
+) -> ScreepsResult<T> {
+    let start = Instant::now();
+    let result = request.await;
+    let elapsed = start.elapsed();
+    METRICS.record_upstream(endpoint, elapsed.as_secs_f64(), result.is_ok());
+    let _ = REQUEST_STATS.try_with(|stats| {
+        let mut stats = stats.borrow_mut();
+        stats.upstream_requests += 1;
+        stats.upstream_duration += elapsed;
+    });
+    result
+}
+
+/// 记录当前请求使用了缓存数据，参数为缓存数据已存在的时间
+pub fn record_cache_age(age: Duration) {
+    let _ = REQUEST_STATS.try_with(|stats| {
+        let mut stats = stats.borrow_mut();
+        stats.cache_age = Some(stats.cache_age.map_or(age, |a| a.max(age)));
+    });
+}
+
+/// 执行请求，并统计其中的上游请求和缓存使用
+/// 只统计当前任务中的请求，`tokio::spawn` 出去的任务不计入
+pub async fn with_request_stats<T>(request: impl Future<Output = T>) -> (T, RequestStats) {
+    REQUEST_STATS
+        .scope(RefCell::new(RequestStats::default()), async move {
+            let result = request.await;
+            (result, REQUEST_STATS.with(|stats| *stats.borrow()))
+        })
+        .await
+}
+
+/// 图片绘制计时器，离开作用域时记录耗时
+pub struct RenderTimer {
+    image: &'static str,
+    start: Instant,
+}
+
+impl RenderTimer {
+    pub fn new(image: &'static str) -> Self {
+        RenderTimer {
+            image,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for RenderTimer {
+    fn drop(&mut self) {
+        METRICS.record_render(self.image, self.start.elapsed().as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = Metrics::default();
+        metrics.record_resources(
+            "pla\"yer",
+            &HashMap::from([(
+                "shard3".to_string(),
+                HashMap::from([("energy".to_string(), 1000)]),
+            )]),
+        );
+        metrics.record_upstream("room_objects", 0.2, false);
+        metrics.record_upstream("room_objects", 0.1, true);
+        metrics.record_upstream_failure("room_objects");
+        metrics.record_cache(true);
+
+        let text = metrics.render(|_, _| true);
+        assert!(text.contains(
+            "screeps_resource_amount{username=\"pla\\\"yer\",shard=\"shard3\",resource=\"energy\"} 1000"
+        ));
+        assert!(
+            !metrics
+                .render(|username, _| username == "other")
+                .contains("screeps_resource_amount{")
+        );
+        assert!(text.contains(
+            "screeps_upstream_request_duration_seconds_bucket{endpoint=\"room_objects\",le=\"0.25\"} 2"
+        ));
+        assert!(text.contains(
+            "screeps_upstream_request_duration_seconds_bucket{endpoint=\"room_objects\",le=\"0.05\"} 0"
+        ));
+        assert!(
+            text.contains("screeps_upstream_request_errors_total{endpoint=\"room_objects\"} 2")
+        );
+        assert!(text.contains("screeps_res_cache_requests_total{result=\"hit\"} 1"));
+
+        metrics.remove_resources("PLA\"YER");
+        assert!(
+            !metrics
+                .render(|_, _| true)
+                .contains("screeps_resource_amount{")
+        );
+    }
+
+    #[test]
+    fn test_resource_series_size() {
+        let metrics = Metrics::default();
+        let res = |shard: &str| {
+            HashMap::from([(
+                shard.to_string(),
+                HashMap::from([("energy".to_string(), 1000)]),
+            )])
+        };
+        for i in 0..=RESOURCE_SERIES_SIZE {
+            metrics.record_resources(&format!("player{}", i), &res("shard3"));
+        }
+        let resources = metrics.resources.lock().unwrap();
+        assert_eq!(resources.len(), RESOURCE_SERIES_SIZE);
+        assert!(!resources.contains_key(&("player0".to_string(), "shard3".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_request_stats() {
+        let (result, stats) = with_request_stats(async {
+            track_upstream("test_a", async { Ok(1) }).await?;
+            record_cache_age(Duration::from_secs(5));
+            record_cache_age(Duration::from_secs(2));
+            track_upstream("test_b", async { Ok(2) }).await
+        })
+        .await;
+        assert_eq!(result.unwrap(), 2);
+        assert_eq!(stats.upstream_requests, 2);
+        assert_eq!(stats.cache_age, Some(Duration::from_secs(5)));
+
+        // 不在统计范围内时不记录
+        record_cache_age(Duration::from_secs(1));
+        assert!(track_upstream("test_c", async { Ok(()) }).await.is_ok());
+    }
+}
@@ -0,0 +1,51 @@
+use utoipa::OpenApi;
+
+/// 接口文档，通过 `/openapi.json` 和 `/docs` 访问
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Screeps Dashboard API",
+        description = "Screeps 玩家资源、房间和报告查询接口"
+    ),
+    paths(
+        crate::get_res_handler,
+        crate::get_res_image_handler,
+        crate::get_res_stream_handler,
+        crate::get_res_export_handler,
+        crate::get_user_overview_handler,
+        crate::get_user_overview_image_handler,
+        crate::get_power_creeps_handler,
+        crate::get_user_live_handler,
+        crate::get_user_memory_handler,
+        crate::get_market_orders_handler,
+        crate::get_cpu_handler,
+        crate::get_cpu_image_handler,
+        crate::get_controllers_handler,
+        crate::get_structures_handler,
+        crate::get_defense_handler,
+        crate::get_defense_image_handler,
+        crate::get_creeps_handler,
+        crate::get_minerals_handler,
+        crate::get_balance_plan_handler,
+        crate::get_privacy_handler,
+        crate::post_privacy_handler,
+        crate::post_tokens_handler,
+        crate::delete_tokens_handler,
+        crate::get_report_log_handler,
+        crate::get_metrics_handler,
+        crate::get_healthz_handler,
+        crate::get_readyz_handler,
+        crate::get_version_handler,
+    ),
+    nest((path = "/api/v1", api = crate::v1::V1Doc)),
+    tags(
+        (name = "res", description = "资源查询与导出"),
+        (name = "user", description = "玩家信息"),
+        (name = "rooms", description = "房间信息"),
+        (name = "plan", description = "资源规划"),
+        (name = "reports", description = "定时报告"),
+        (name = "monitoring", description = "监控指标"),
+        (name = "v1", description = "v1 接口，使用统一的响应格式"),
+    )
+)]
+pub struct ApiDoc;
@@ -0,0 +1,214 @@
+use crate::{
+    config::BalanceConfig,
+    error::{ErrorCode, error},
+    res::query_room_objects,
+};
+use screeps_rust_api::{RoomObject, ScreepsApi, ScreepsResult};
+use serde::Serialize;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// 一个房间可用于平衡的资源
+pub struct RoomStock {
+    pub room: String,
+    /// storage 和 terminal 中的资源
+    pub store: HashMap<String, i32>,
+}
+
+/// 一次 terminal 传送
+#[derive(Serialize, Debug, PartialEq, ToSchema)]
+pub struct Transfer {
+    pub resource: String,
+    pub from: String,
+    pub to: String,
+    pub amount: i32,
+    pub distance: u32,
+    /// 传送消耗的能量
+    pub energy_cost: i32,
+}
+
+/// 资源平衡计划
+#[derive(Serialize, ToSchema)]
+pub struct BalancePlan {
+    pub shard: String,
+    pub transfers: Vec<Transfer>,
+    pub total_energy_cost: i32,
+}
+
+/// 解析房间名为世界坐标，如 `W1N1` -> (-2, -2)，`E0S0` -> (0, 0)
+fn room_coord(room: &str) -> Option<(i32, i32)> {
+    let split = room.get(1..)?.find(['N', 'S'])? + 1;
+    let (horizontal, vertical) = room.split_at(split);
+    let x: i32 = horizontal[1..].parse().ok()?;
+    let y: i32 = vertical[1..].parse().ok()?;
+    let x = match &horizontal[..1] {
+        "W" => -x - 1,
+        "E" => x,
+        _ => return None,
+    };
+    let y = match &vertical[..1] {
+        "N" => -y - 1,
+        "S" => y,
+        _ => return None,
+    };
+    Some((x, y))
+}
+
+/// 计算两个房间的直线距离，不考虑世界边缘的环绕
+pub fn room_distance(a: &str, b: &str) -> Option<u32> {
+    let (ax, ay) = room_coord(a)?;
+    let (bx, by) = room_coord(b)?;
+    Some(ax.abs_diff(bx).max(ay.abs_diff(by)))
+}
+
+/// terminal 传送资源的能量消耗
+pub fn transfer_cost(amount: i32, distance: u32) -> i32 {
+    (amount as f64 * (1.0 - (-(distance as f64) / 30.0).exp())).ceil() as i32
+}
+
+/// 根据目标数量生成传送计划
+/// 每个缺少资源的房间从距离最近的富余房间拉取资源，缺口越大越优先
+pub fn plan_transfers(rooms: &[RoomStock], config: &BalanceConfig) -> Vec<Transfer> {
+    let mut resources: Vec<_> = config.targets.iter().collect();
+    resources.sort();
+
+    let mut transfers = Vec::new();
+    for (resource, target) in resources {
+        let amount_of = |room: &RoomStock| room.store.get(resource).copied().unwrap_or(0);
+        let mut surplus: Vec<(&str, i32)> = rooms
+            .iter()
+            .map(|room| (room.room.as_str(), amount_of(room) - target))
+            .filter(|(_, amount)| *amount > 0)
+            .collect();
+        let mut deficits: Vec<(&str, i32)> = rooms
+            .iter()
+            .map(|room| (room.room.as_str(), target - amount_of(room)))
+            .filter(|(_, amount)| *amount > 0)
+            .collect();
+        deficits.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        for (to, mut deficit) in deficits {
+            while deficit >= config.min_transfer {
+                // 找距离最近且富余足够的房间
+                let Some((index, distance)) = surplus
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, amount))| *amount >= config.min_transfer)
+                    .filter_map(|(i, (from, _))| Some((i, room_distance(from, to)?)))
+                    .min_by_key(|(i, distance)| (*distance, surplus[*i].0))
+                else {
+                    break;
+                };
+                let amount = deficit.min(surplus[index].1);
+                surplus[index].1 -= amount;
+                deficit -= amount;
+                transfers.push(Transfer {
+                    resource: resource.clone(),
+                    from: surplus[index].0.to_string(),
+                    to: to.to_string(),
+                    amount,
+                    distance,
+                    energy_cost: transfer_cost(amount, distance),
+                });
+            }
+        }
+    }
+    transfers
+}
+
+/// 查询玩家指定 shard 的资源平衡计划
+/// 只有建造了 terminal 的房间参与平衡
+/// 参数：
+/// - username: 玩家名称
+/// - shard: 目标 shard，terminal 无法跨 shard 传送，不支持 `all`
+pub async fn query_balance_plan(
+    api: &ScreepsApi,
+    username: &str,
+    shard: &str,
+    config: &BalanceConfig,
+) -> ScreepsResult<BalancePlan> {
+    if shard == "all" {
+        return Err(error(ErrorCode::BadRequest, "资源平衡需要指定 shard"));
+    }
+
+    let mut rooms = Vec::new();
+    for room_objects in query_room_objects(api, username, shard).await? {
+        let mut store = HashMap::new();
+        let mut has_terminal = false;
+        for room_object in room_objects.objects {
+            let resources = match &room_object {
+                RoomObject::Storage(storage) => &storage.store,
+                RoomObject::Terminal(terminal) => {
+                    has_terminal = true;
+                    &terminal.store
+                }
+                _ => continue,
+            };
+            for (resource_type, amount) in resources.iter() {
+                *store.entry(resource_type.to_string()).or_insert(0) += amount.unwrap_or(0);
+            }
+        }
+        if has_terminal {
+            rooms.push(RoomStock {
+                room: room_objects.room,
+                store,
+            });
+        }
+    }
+
+    let transfers = plan_transfers(&rooms, config);
+    Ok(BalancePlan {
+        shard: shard.to_string(),
+        total_energy_cost: transfers.iter().map(|t| t.energy_cost).sum(),
+        transfers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_room_distance() {
+        assert_eq!(room_coord("W1N1"), Some((-2, -2)));
+        assert_eq!(room_coord("E0S0"), Some((0, 0)));
+        assert_eq!(room_coord("sim"), None);
+        assert_eq!(room_coord(""), None);
+        assert_eq!(room_distance("W1N1", "W1N1"), Some(0));
+        assert_eq!(room_distance("W1N1", "W5N3"), Some(4));
+        assert_eq!(room_distance("W0N0", "E0S0"), Some(1));
+    }
+
+    #[test]
+    fn test_transfer_cost() {
+        assert_eq!(transfer_cost(1000, 0), 0);
+        assert_eq!(transfer_cost(1000, 1), 33);
+        assert_eq!(transfer_cost(1000, 10), 284);
+    }
+
+    #[test]
+    fn test_plan_transfers() {
+        let stock = |room: &str, energy: i32| RoomStock {
+            room: room.to_string(),
+            store: HashMap::from([("energy".to_string(), energy)]),
+        };
+        let rooms = vec![
+            stock("W1N1", 300_000),
+            stock("W9N9", 300_000),
+            stock("W2N1", 50_000),
+        ];
+        let config = BalanceConfig::default();
+        let transfers = plan_transfers(&rooms, &config);
+        assert_eq!(
+            transfers,
+            vec![Transfer {
+                resource: "energy".to_string(),
+                from: "W1N1".to_string(),
+                to: "W2N1".to_string(),
+                amount: 50_000,
+                distance: 1,
+                energy_cost: transfer_cost(50_000, 1),
+            }]
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use crate::{
+    auth,
+    config::{Config, PrivacyConfig},
+    error::{ErrorCode, error},
+    history,
+    metrics::METRICS,
+    res::ShardRes,
+    tokens,
+};
+use axum::{
+    extract::{Query, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use screeps_rust_api::{ScreepsError, ScreepsResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{Arc, LazyLock, Mutex},
+};
+use utoipa::ToSchema;
+
+/// 通过资源接口查询统计数据（`source=stats`）时检查的路径，统计数据不属于资源
+const STATS_PATH: &str = "/res?source=stats";
+
+/// 玩家公开设置的保存路径
+const PRIVACY_PATH: &str = "data/privacy.json";
+
+/// 只公开资源总量时允许访问的接口
+const RESOURCE_PATHS: [&str; 3] = ["/res", "/res/image", "/res/stream"];
+
+/// 包含房间明细的接口
+const ROOM_PATHS: [&str; 2] = ["/res/export", "/plan/balance"];
+
+/// 实时推送的接口
+const REALTIME_PATHS: [&str; 3] = ["/res/stream", "/user/live", "/ws"];
+
+/// 可以返回延迟数据的接口，玩家设置了延迟时只允许访问这些接口
+const DELAYED_PATHS: [&str; 2] = ["/res", "/res/image"];
+
+/// 玩家自行设置的数据公开范围
+#[derive(Serialize, Deserialize, ToSchema, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct PrivacySettings {
+    /// 只公开资源总量，`/res`、`/res/image`、`/res/stream` 以外的接口不返回该玩家的数据
+    pub resources_only: bool,
+    /// 不公开按房间的明细，`/rooms/*`、`/res/export`、`/plan/balance` 不返回该玩家的数据
+    pub hide_rooms: bool,
+    /// 资源数据延迟的秒数，0 表示不延迟，延迟时只有 `/res`、`/res/image` 返回延迟的数据，其他接口不返回该玩家的数据
+    pub delay: u64,
+}
+
+impl PrivacySettings {
+    /// 是否公开所有数据
+    pub fn is_public(&self) -> bool {
+        *self == PrivacySettings::default()
+    }
+}
+
+/// 玩家注册请求，使用玩家自己的 Screeps token 证明身份
+#[derive(Deserialize, ToSchema)]
+pub struct PrivacyRegistration {
+    /// 玩家的 Screeps API token，只用于验证身份，不会保存
+    pub token: String,
+    #[serde(flatten)]
+    pub settings: PrivacySettings,
+}
+
+/// 玩家的数据公开设置
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct PlayerPrivacy {
+    pub username: String,
+    pub settings: PrivacySettings,
+    /// 设置时间，unix 时间戳（秒），未注册的玩家为 null
+    pub updated_at: Option<i64>,
+}
+
+/// 已注册玩家的设置，键为小写的玩家名称
+static PLAYERS: LazyLock<Mutex<HashMap<String, PlayerPrivacy>>> = LazyLock::new(|| {
+    let players = fs::read_to_string(PRIVACY_PATH)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    Mutex::new(players)
+});
+
+/// Screeps 玩家名称不区分大小写
+pub fn normalize(username: &str) -> String {
+    username.to_lowercase()
+}
+
+/// 保存所有玩家的设置
+fn save(players: &HashMap<String, PlayerPrivacy>) -> std::io::Result<()> {
+    if let Some(dir) = Path::new(PRIVACY_PATH).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(PRIVACY_PATH, serde_json::to_string_pretty(players)?)
+}
+
+/// 获取玩家的公开设置，未注册时返回 None
+pub fn settings(username: &str) -> Option<PrivacySettings> {
+    PLAYERS
+        .lock()
+        .unwrap()
+        .get(&normalize(username))
+        .map(|player| player.settings.clone())
+}
+
+/// 玩家设置的资源数据延迟秒数
+pub fn delay(username: &str) -> Option<u64> {
+    settings(username)
+        .map(|settings| settings.delay)
+        .filter(|&delay| delay > 0)
+}
+
+/// 是否公开玩家所有的数据
+pub fn is_public(username: &str) -> bool {
+    settings(username).is_none_or(|settings| settings.is_public())
+}
+
+/// 检查是否允许查询玩家的数据，不允许时返回原因
+/// 参数：
+/// - path: 请求的接口路径，`/api/v1` 下的接口与不带前缀的接口相同
+pub fn check(config: &PrivacyConfig, username: &str, path: &str) -> Result<(), &'static str> {
+    let name = normalize(username);
+    let listed = |list: &[String]| list.iter().any(|u| normalize(u) == name);
+    if listed(&config.deny) || (!config.allow.is_empty() && !listed(&config.allow)) {
+        return Err("服务不提供该玩家的数据");
+    }
+
+    let Some(settings) = settings(username) else {
+        return Ok(());
+    };
+    let path = path.strip_prefix("/api/v1").unwrap_or(path);
+    if settings.resources_only && !RESOURCE_PATHS.contains(&path) {
+        return Err("玩家只公开资源总量");
+    }
+    if settings.hide_rooms && (path.starts_with("/rooms/") || ROOM_PATHS.contains(&path)) {
+        return Err("玩家不公开房间明细");
+    }
+    if settings.delay > 0 && REALTIME_PATHS.contains(&path) {
+        return Err("玩家的数据有延迟，不提供实时推送");
+    }
+    if settings.delay > 0 && !DELAYED_PATHS.contains(&path) {
+        return Err("玩家的数据有延迟，只能查询资源总量");
+    }
+    Ok(())
+}
+
+/// 玩家隐私中间件，检查查询参数中的 `username` 是否允许查询
+pub async fn privacy_middleware(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let query: HashMap<String, String> = Query::try_from_uri(request.uri())
+        .map(|Query(query)| query)
+        .unwrap_or_default();
+    if let Some(username) = query.get("username") {
+        let path = request.uri().path();
+        let checked = if query.get("source").is_some_and(|source| source == "stats") {
+            STATS_PATH
+        } else {
+            path
+        };
+        // 玩家总是可以查询自己的设置
+        if path.strip_prefix("/api/v1").unwrap_or(path) != "/privacy"
+            && let Err(message) = check(&config.privacy, username, checked)
+        {
+            return auth::reject(path, StatusCode::FORBIDDEN, "forbidden", message);
+        }
+    }
+    next.run(request).await
+}
+
+/// 玩家使用自己的 token 注册并设置数据公开范围
+pub async fn register(registration: PrivacyRegistration) -> ScreepsResult<PlayerPrivacy> {
+    let username = tokens::query_token_user(&reqwest::Client::new(), &registration.token)
+        .await?
+        .username;
+    let player = PlayerPrivacy {
+        username: username.clone(),
+        settings: registration.settings,
+        updated_at: Some(chrono::Utc::now().timestamp()),
+    };
+    let mut players = PLAYERS.lock().unwrap();
+    players.insert(normalize(&username), player.clone());
+    save(&players).map_err(|e| ScreepsError::Api(format!("保存设置失败: {}", e)))?;
+    if !player.settings.is_public() {
+        METRICS.remove_resources(&username);
+    }
+    Ok(player)
+}
+
+/// 查询玩家的数据公开设置，未注册的玩家返回默认设置
+pub fn query_privacy(username: &str) -> PlayerPrivacy {
+    PLAYERS
+        .lock()
+        .unwrap()
+        .get(&normalize(username))
+        .cloned()
+        .unwrap_or(PlayerPrivacy {
+            username: username.to_string(),
+            settings: PrivacySettings::default(),
+            updated_at: None,
+        })
+}
+
+/// 读取延迟之前的资源数据，每个 shard 返回不晚于 `delay` 秒之前的最后一条历史记录
+/// 需要开启历史记录
+pub fn delayed_res(username: &str, target_shard: &str, delay: u64) -> ScreepsResult<ShardRes> {
+    if !history::is_enabled() {
+        return Err(error(
+            ErrorCode::Unavailable,
+            "玩家的数据有延迟，需要开启历史记录",
+        ));
+    }
+    let io_error = |e: std::io::Error| ScreepsError::Api(format!("读取历史记录失败: {}", e));
+    let shards: Vec<String> = if target_shard == "all" {
+        history::player_shards("res", username).map_err(io_error)?
+    } else {
+        vec![target_shard.to_string()]
+    };
+
+    let until = chrono::Utc::now().timestamp() - delay as i64;
+    let mut result = ShardRes::new();
+    for shard in shards {
+        let records = history::load::<HashMap<String, i32>>(
+            "res",
+            &history::player_key(username, &shard),
+            None,
+            Some(until),
+        )
+        .map_err(io_error)?;
+        if let Some(record) = records.into_iter().last() {
+            result.insert(shard, record.value);
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let config = PrivacyConfig {
+            allow: Vec::new(),
+            deny: vec!["Hidden".to_string()],
+        };
+        assert!(check(&config, "hidden", "/res").is_err());
+        assert!(check(&config, "player", "/rooms/creeps").is_ok());
+
+        let config = PrivacyConfig {
+            allow: vec!["player".to_string()],
+            deny: Vec::new(),
+        };
+        assert!(check(&config, "Player", "/api/v1/res").is_ok());
+        assert!(check(&config, "other", "/res").is_err());
+
+        PLAYERS.lock().unwrap().insert(
+            "private".to_string(),
+            PlayerPrivacy {
+                username: "Private".to_string(),
+                settings: PrivacySettings {
+                    resources_only: false,
+                    hide_rooms: true,
+                    delay: 3600,
+                },
+                updated_at: Some(0),
+            },
+        );
+        let config = PrivacyConfig::default();
+        assert!(check(&config, "private", "/res").is_ok());
+        assert!(check(&config, "private", "/res/image").is_ok());
+        assert!(check(&config, "private", "/user/overview").is_err());
+        assert!(check(&config, "private", STATS_PATH).is_err());
+        assert!(check(&config, "private", "/api/v1/rooms/structures").is_err());
+        assert!(check(&config, "private", "/res/export").is_err());
+        assert!(check(&config, "private", "/ws").is_err());
+        assert_eq!(delay("Private"), Some(3600));
+        assert!(!is_public("private"));
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{
+    auth::{self, ApiKey},
+    config::{Config, QuotaConfig},
+};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    sync::{Arc, LazyLock, Mutex},
+    time::Instant,
+};
+
+/// 不需要 API key 但会请求上游的接口，同样需要限流
+const LIMITED_PUBLIC_PATHS: [&str; 4] =
+    ["/privacy", "/api/v1/privacy", "/tokens", "/api/v1/tokens"];
+
+/// 超过该数量时清理已经补满的令牌桶
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+/// 令牌桶
+struct Bucket {
+    quota: QuotaConfig,
+    tokens: f64,
+    updated: Instant,
+    /// 请求前预扣的令牌数，为上一个请求的实际消耗
+    estimate: f64,
+}
+
+impl Bucket {
+    fn new(quota: QuotaConfig, now: Instant) -> Self {
+        Bucket {
+            quota,
+            tokens: quota.capacity,
+            updated: now,
+            estimate: 1.0,
+        }
+    }
+
+    /// 按经过的时间补充令牌
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.quota.refill).min(self.quota.capacity);
+        self.updated = now;
+    }
+
+    /// 至少有一个令牌时允许请求，否则返回需要等待的秒数
+    fn check(&mut self, now: Instant) -> Result<(), u64> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            return Ok(());
+        }
+        if self.quota.refill <= 0.0 {
+            return Err(u64::MAX);
+        }
+        Err(((1.0 - self.tokens) / self.quota.refill).ceil() as u64)
+    }
+
+    /// 检查令牌并预扣估计的消耗，返回预扣的令牌数
+    /// 并发的请求各自预扣，避免在第一个请求结算之前全部通过检查
+    fn reserve(&mut self, now: Instant) -> Result<f64, u64> {
+        self.check(now)?;
+        self.tokens -= self.estimate;
+        Ok(self.estimate)
+    }
+
+    /// 请求结束后按实际消耗结算预扣的令牌，实际消耗作为下一个请求的估计
+    fn settle(&mut self, reserved: f64, cost: usize) {
+        self.tokens += reserved - cost as f64;
+        self.estimate = cost as f64;
+    }
+}
+
+/// 每个客户端的令牌桶，键为 `key:<名称>` 或 `ip:<地址>`
+static BUCKETS: LazyLock<Mutex<HashMap<String, Bucket>>> = LazyLock::new(Default::default);
+
+tokio::task_local! {
+    /// 当前请求查询的房间数
+    static ROOMS: Cell<usize>;
+}
+
+/// 记录当前请求向上游查询的房间数，用于计算请求的消耗
+pub fn record_rooms(count: usize) {
+    let _ = ROOMS.try_with(|rooms| rooms.set(rooms.get() + count));
+}
+
+/// 检查客户端的令牌桶并预扣估计的消耗，桶中没有令牌时返回需要等待的秒数
+/// 修改了配置的额度时重新创建令牌桶
+fn reserve(client: &str, quota: QuotaConfig, now: Instant) -> Result<f64, u64> {
+    let mut buckets = BUCKETS.lock().unwrap();
+    if buckets.len() > MAX_IDLE_BUCKETS {
+        buckets.retain(|_, bucket| {
+            bucket.refill(now);
+            bucket.tokens < bucket.quota.capacity
+        });
+    }
+    let bucket = buckets
+        .entry(client.to_string())
+        .or_insert_with(|| Bucket::new(quota, now));
+    if bucket.quota != quota {
+        *bucket = Bucket::new(quota, now);
+    }
+    bucket.reserve(now)
+}
+
+/// 结算客户端预扣的令牌，令牌可以扣成负数，之后的请求需要等待令牌补充
+fn settle(client: &str, reserved: f64, cost: usize) {
+    if let Some(bucket) = BUCKETS.lock().unwrap().get_mut(client) {
+        bucket.settle(reserved, cost);
+    }
+}
+
+/// 限流的客户端，开启限流时由中间件放入请求的 extensions
+/// WebSocket 和 SSE 的后台查询使用订阅者的客户端计费
+#[derive(Clone)]
+pub struct Client {
+    id: String,
+    quota: QuotaConfig,
+}
+
+impl Client {
+    /// 使用该客户端的令牌执行查询，按向上游查询的房间数扣除令牌，至少扣除一个
+    /// 桶中没有令牌时不执行，返回需要等待的秒数
+    pub async fn run<F: Future>(&self, request: F) -> Result<F::Output, u64> {
+        let reserved = reserve(&self.id, self.quota, Instant::now())?;
+        let (output, rooms) = ROOMS
+            .scope(Cell::new(0), async {
+                let output = request.await;
+                (output, ROOMS.with(Cell::get))
+            })
+            .await;
+        settle(&self.id, reserved, rooms.max(1));
+        Ok(output)
+    }
+}
+
+/// 限流中间件，按 API key 或客户端 IP 使用令牌桶限流
+/// 请求前桶中至少需要一个令牌并预扣上一个请求的消耗，请求结束后按向上游查询的房间数结算，至少扣除一个
+/// 超出限制时返回 429 和 `Retry-After`
+pub async fn rate_limit_middleware(
+    State(config): State<Arc<Config>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    if !config.rate_limit.enabled
+        || (auth::is_public(&path) && !LIMITED_PUBLIC_PATHS.contains(&path.as_str()))
+    {
+        return next.run(request).await;
+    }
+
+    let key = request.extensions().get::<ApiKey>();
+    let quota = key
+        .and_then(|key| key.quota)
+        .unwrap_or(config.rate_limit.quota);
+    let id = match key {
+        Some(key) => format!("key:{}", key.name),
+        None => match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+            None => "ip:unknown".to_string(),
+        },
+    };
+    let client = Client { id, quota };
+    request.extensions_mut().insert(client.clone());
+
+    match client.run(next.run(request)).await {
+        Ok(response) => response,
+        Err(retry_after) => {
+            let mut response = auth::reject(
+                &path,
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                "请求过于频繁",
+            );
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_bucket() {
+        let quota = QuotaConfig {
+            capacity: 10.0,
+            refill: 2.0,
+        };
+        let now = Instant::now();
+        let mut bucket = Bucket::new(quota, now);
+        assert_eq!(bucket.check(now), Ok(()));
+
+        // 查询了 15 个房间，令牌扣成负数
+        bucket.tokens -= 15.0;
+        assert_eq!(bucket.check(now), Err(3));
+        assert_eq!(bucket.check(now + Duration::from_secs(2)), Err(1));
+        assert_eq!(bucket.check(now + Duration::from_secs(3)), Ok(()));
+
+        // 补充不超过容量
+        bucket.check(now + Duration::from_secs(60)).unwrap();
+        assert_eq!(bucket.tokens, 10.0);
+    }
+
+    #[test]
+    fn test_reserve() {
+        let quota = QuotaConfig {
+            capacity: 10.0,
+            refill: 0.0,
+        };
+        let now = Instant::now();
+        let mut bucket = Bucket::new(quota, now);
+        // 第一个请求预扣 1 个，实际查询了 6 个房间
+        let reserved = bucket.reserve(now).unwrap();
+        bucket.settle(reserved, 6);
+        assert_eq!(bucket.tokens, 4.0);
+
+        // 并发的请求按上一个请求的消耗预扣，第二个请求在结算之前被拒绝
+        let first = bucket.reserve(now).unwrap();
+        assert_eq!(first, 6.0);
+        assert!(bucket.reserve(now).is_err());
+        bucket.settle(first, 2);
+        assert_eq!(bucket.tokens, 2.0);
+        assert_eq!(bucket.estimate, 2.0);
+    }
+}
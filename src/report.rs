@@ -0,0 +1,129 @@
+use crate::{
+    config::{Config, ReportConfig, ReportKind},
+    cron::CronSchedule,
+    defense::draw_defense_image,
+    res::draw_res_image,
+    user::draw_user_overview_image,
+    webhook::send_webhook_file,
+};
+use chrono::prelude::*;
+use screeps_rust_api::ScreepsApi;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+/// 报告日志最多保留的条数
+const REPORT_LOG_SIZE: usize = 100;
+
+/// 一次报告发送记录
+#[derive(Serialize, Clone, ToSchema)]
+pub struct ReportLogEntry {
+    pub name: String,
+    pub time: String,
+    pub success: bool,
+    /// 上传尝试次数
+    pub attempts: u32,
+    pub error: Option<String>,
+}
+
+/// 最近的报告发送记录，新的记录在后
+pub type ReportLog = Arc<Mutex<VecDeque<ReportLogEntry>>>;
+
+/// 启动定时报告任务，每分钟检查一次 cron 表达式，没有有效的报告配置时不启动
+pub fn spawn_report_job(
+    api: Arc<ScreepsApi>,
+    config: Arc<Config>,
+    log: ReportLog,
+) -> Option<JoinHandle<()>> {
+    let mut schedules = Vec::new();
+    for report in &config.reports {
+        match CronSchedule::parse(&report.cron) {
+            Ok(schedule) => schedules.push((schedule, report.clone())),
+            Err(e) => eprintln!("Invalid cron for report {}: {}", report.name, e),
+        }
+    }
+    if schedules.is_empty() {
+        return None;
+    }
+
+    Some(tokio::spawn(async move {
+        loop {
+            // 等到下一分钟开始
+            let now = Local::now();
+            let elapsed = now.second() as u64 * 1000 + now.timestamp_subsec_millis() as u64;
+            tokio::time::sleep(Duration::from_millis(
+                60_000u64.saturating_sub(elapsed).max(1),
+            ))
+            .await;
+
+            let now = Local::now();
+            for (schedule, report) in &schedules {
+                if schedule.matches(&now) {
+                    tokio::spawn(run_report(api.clone(), report.clone(), log.clone()));
+                }
+            }
+        }
+    }))
+}
+
+/// 绘制报告图片，返回 PNG 图片
+async fn render_report(api: &ScreepsApi, report: &ReportConfig) -> Result<Vec<u8>, String> {
+    let result = match report.kind {
+        ReportKind::Res => draw_res_image(api, &report.username, &report.shard).await,
+        ReportKind::Overview => draw_user_overview_image(api, &report.username).await,
+        ReportKind::Defense => draw_defense_image(api, &report.username, &report.shard).await,
+    };
+    result.map_err(|e| e.to_string())
+}
+
+/// 绘制并上传一次报告，上传失败时按 1、2、4... 秒间隔重试
+async fn run_report(api: Arc<ScreepsApi>, report: ReportConfig, log: ReportLog) {
+    let mut attempts = 0;
+    let result = match render_report(&api, &report).await {
+        Ok(content) => {
+            let client = reqwest::Client::new();
+            let time_str = Local::now().format("%Y/%m/%d %H:%M").to_string();
+            let message = format!("{} {}", report.name, time_str);
+            let file_name = format!("{}.png", report.name);
+            loop {
+                attempts += 1;
+                match send_webhook_file(
+                    &client,
+                    &report.webhook,
+                    &message,
+                    &file_name,
+                    content.clone(),
+                )
+                .await
+                {
+                    Ok(()) => break Ok(()),
+                    Err(e) if attempts > report.retries => break Err(e.to_string()),
+                    Err(_) => {
+                        tokio::time::sleep(Duration::from_secs(1 << (attempts - 1).min(6))).await;
+                    }
+                }
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = &result {
+        eprintln!("Failed to send report {}: {}", report.name, e);
+    }
+    let mut log = log.lock().unwrap();
+    if log.len() >= REPORT_LOG_SIZE {
+        log.pop_front();
+    }
+    log.push_back(ReportLogEntry {
+        name: report.name,
+        time: Local::now().to_rfc3339(),
+        success: result.is_ok(),
+        attempts,
+        error: result.err(),
+    });
+}
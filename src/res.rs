@@ -4,40 +4,125 @@ use crate::{
         BASE_RES, C_BLUE_RES, C_GREEN_RES, C_GREY_RES, C_PINK_RES, C_YELLOW_RES, POWER_RES,
         res_color_map,
     },
-    utils::{draw_res, draw_res_text, merge_res, parse_color},
+    error::{ErrorCode, error},
+    history, ingest, live,
+    metrics::{METRICS, RenderTimer, record_cache_age, track_upstream},
+    privacy, ratelimit,
+    utils::{draw_res, draw_res_text, merge_res, parse_color, render_png},
 };
-use chrono::prelude::*;
-use plotters::prelude::*;
 use screeps_rust_api::{RoomObject, ScreepsApi, ScreepsError, ScreepsResult};
-use std::collections::HashMap;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
 
-/// 查询玩家指定shard具有的资源
-/// 参数：
-/// - username: 玩家名称
-/// - target_shard: 目标 shard，传 `all` 表示所有 shard
-pub async fn query_res(
-    api: &ScreepsApi,
-    username: &str,
-    target_shard: &str,
-) -> ScreepsResult<HashMap<String, HashMap<String, i32>>> {
-    let mut result = HashMap::new();
+/// 玩家资源，shard -> 资源类型 -> 数量
+pub type ShardRes = HashMap<String, HashMap<String, i32>>;
+
+/// 检查 shard 名称，只允许字母、数字、`_` 和 `-`，`all` 表示所有 shard
+pub fn check_shard(shard: &str) -> ScreepsResult<()> {
+    if !shard.is_empty()
+        && shard
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+    {
+        Ok(())
+    } else {
+        Err(error(ErrorCode::BadRequest, "shard 名称不合法"))
+    }
+}
+
+/// 资源查询缓存最多保存的条目数
+const RES_CACHE_SIZE: usize = 1024;
+
+/// 资源查询缓存，键为 (玩家名称, shard)
+static RES_CACHE: LazyLock<Mutex<HashMap<(String, String), (Instant, ShardRes)>>> =
+    LazyLock::new(Default::default);
 
-    // 先根据玩家信息查玩家的 id
-    let user_info = api.get_user_info_by_name(username).await?;
+/// 一个房间的房间对象
+pub struct RoomObjects {
+    pub shard: String,
+    pub room: String,
+    pub objects: Vec<RoomObject>,
+}
+
+/// 根据玩家名称查询玩家 id
+pub async fn query_user_id(api: &ScreepsApi, username: &str) -> ScreepsResult<String> {
+    let user_info = track_upstream("user_find", api.get_user_info_by_name(username)).await?;
     if user_info.base_data.ok == None || user_info.base_data.ok.unwrap() != 1 {
-        return Err(ScreepsError::Api("玩家不存在".to_string()));
+        return Err(error(ErrorCode::NotFound, "玩家不存在"));
     }
+    Ok(user_info.user.unwrap()._id)
+}
 
-    let user_id = user_info.user.unwrap()._id;
-    // 再根据玩家 id 查玩家所有房间
-    let user_rooms = api.get_user_rooms(&user_id).await?;
+/// 根据玩家 id 查询玩家所有房间，按 shard 分组
+pub async fn query_user_rooms(
+    api: &ScreepsApi,
+    user_id: &str,
+) -> ScreepsResult<HashMap<String, Vec<String>>> {
+    let user_rooms = track_upstream("user_rooms", api.get_user_rooms(user_id)).await?;
     if user_rooms.base_data.ok.unwrap() != 1 {
-        return Err(ScreepsError::Api("玩家没有房间".to_string()));
+        METRICS.record_upstream_failure("user_rooms");
+        return Err(error(ErrorCode::NotFound, "玩家没有房间"));
     }
+    Ok(user_rooms.shards.unwrap().into_iter().collect())
+}
+
+/// 查询指定 shard 当前的游戏 tick
+pub async fn query_game_time(api: &ScreepsApi, shard: &str) -> ScreepsResult<u64> {
+    let time = track_upstream("game_time", api.get_game_time(shard)).await?;
+    if time.base_data.ok.unwrap_or(0) != 1 {
+        METRICS.record_upstream_failure("game_time");
+        return Err(ScreepsError::Api("获取游戏时间失败".to_string()));
+    }
+    Ok(time.time.unwrap_or(0))
+}
+
+/// 查询房间列表涉及的所有 shard 的游戏 tick，每个 shard 只查询一次
+pub async fn query_game_times(
+    api: &ScreepsApi,
+    room_objects_list: &[RoomObjects],
+) -> ScreepsResult<HashMap<String, u64>> {
+    let mut game_times = HashMap::new();
+    for room_objects in room_objects_list {
+        if !game_times.contains_key(&room_objects.shard) {
+            let time = query_game_time(api, &room_objects.shard).await?;
+            game_times.insert(room_objects.shard.clone(), time);
+        }
+    }
+    Ok(game_times)
+}
+
+/// 查询玩家指定 shard 所有房间的房间对象
+/// 参数：
+/// - username: 玩家名称
+/// - target_shard: 目标 shard，传 `all` 表示所有 shard
+pub async fn query_room_objects(
+    api: &ScreepsApi,
+    username: &str,
+    target_shard: &str,
+) -> ScreepsResult<Vec<RoomObjects>> {
+    check_shard(target_shard)?;
+    // 先根据玩家信息查玩家的 id，再根据玩家 id 查玩家所有房间
+    let user_id = query_user_id(api, username).await?;
+    let user_rooms = query_user_rooms(api, &user_id).await?;
+    fetch_room_objects(api, &user_rooms, target_shard).await
+}
 
+/// 批量查询房间对象
+/// 参数：
+/// - user_rooms: 按 shard 分组的房间列表
+/// - target_shard: 目标 shard，传 `all` 表示所有 shard
+pub async fn fetch_room_objects(
+    api: &ScreepsApi,
+    user_rooms: &HashMap<String, Vec<String>>,
+    target_shard: &str,
+) -> ScreepsResult<Vec<RoomObjects>> {
     // 收集所有需要查询的房间和 shard 信息
     let mut room_shard_pairs = Vec::new();
-    for (shard, rooms) in user_rooms.shards.unwrap().iter() {
+    for (shard, rooms) in user_rooms.iter() {
         if target_shard != "all" && shard != target_shard {
             continue;
         }
@@ -46,19 +131,36 @@ pub async fn query_res(
         }
     }
 
+    // 开启了 socket 数据接入时优先使用内存中的房间对象
+    let mut result = Vec::new();
+    room_shard_pairs.retain(|(room, shard)| match ingest::room_objects(shard, room) {
+        Some(objects) => {
+            result.push(RoomObjects {
+                shard: shard.clone(),
+                room: room.clone(),
+                objects,
+            });
+            false
+        }
+        None => true,
+    });
+
+    ratelimit::record_rooms(room_shard_pairs.len());
+
     // 创建所有 future
     let futures: Vec<_> = room_shard_pairs
         .iter()
-        .map(|(room, shard)| api.get_room_objects(room, shard))
+        .map(|(room, shard)| track_upstream("room_objects", api.get_room_objects(room, shard)))
         .collect();
 
     // 执行所有请求
     let responses = futures::future::join_all(futures).await;
     // 处理响应
-    for (response, (room, shard)) in responses.into_iter().zip(room_shard_pairs.iter()) {
+    for (response, (room, shard)) in responses.into_iter().zip(room_shard_pairs.into_iter()) {
         match response {
             Ok(room_objects) => {
                 if room_objects.base_data.ok.unwrap() != 1 {
+                    METRICS.record_upstream_failure("room_objects");
                     eprintln!(
                         "Failed to fetch objects for room {} in shard {}, reason: {}",
                         room,
@@ -67,41 +169,11 @@ pub async fn query_res(
                     );
                     continue;
                 }
-                let shard_res_map = result.entry(shard.clone()).or_insert_with(HashMap::new);
-                for room_object in room_objects.objects.unwrap() {
-                    match room_object {
-                        RoomObject::Storage(storage) => {
-                            for (resource_type, amount) in storage.store.iter() {
-                                let amount = amount.unwrap_or(0);
-                                shard_res_map
-                                    .entry(resource_type.to_string())
-                                    .and_modify(|a| *a += amount)
-                                    .or_insert(amount);
-                            }
-                        }
-                        RoomObject::Terminal(terminal) => {
-                            for (resource_type, amount) in terminal.store.iter() {
-                                let amount = amount.unwrap_or(0);
-                                shard_res_map
-                                    .entry(resource_type.to_string())
-                                    .and_modify(|a| *a += amount)
-                                    .or_insert(amount);
-                            }
-                        }
-                        RoomObject::Factory(link) => {
-                            for (resource_type, amount) in link.store.iter() {
-                                let amount = amount.unwrap_or(0);
-                                shard_res_map
-                                    .entry(resource_type.to_string())
-                                    .and_modify(|a| *a += amount)
-                                    .or_insert(amount);
-                            }
-                        }
-                        _ => {
-                            continue;
-                        }
-                    }
-                }
+                result.push(RoomObjects {
+                    shard,
+                    room,
+                    objects: room_objects.objects.unwrap(),
+                });
             }
             Err(e) => {
                 eprintln!(
@@ -116,112 +188,247 @@ pub async fn query_res(
     Ok(result)
 }
 
+/// 一个建筑中的一种资源
+#[derive(Serialize)]
+pub struct ResRow {
+    pub shard: String,
+    pub room: String,
+    pub structure: String,
+    pub resource: String,
+    pub amount: i32,
+}
+
+/// 查询玩家指定shard每个房间每个建筑中的资源
+/// 统计 storage、terminal 和 factory
+/// 参数：
+/// - username: 玩家名称
+/// - target_shard: 目标 shard，传 `all` 表示所有 shard
+pub async fn query_res_rows(
+    api: &ScreepsApi,
+    username: &str,
+    target_shard: &str,
+) -> ScreepsResult<Vec<ResRow>> {
+    let mut rows = Vec::new();
+
+    for room_objects in query_room_objects(api, username, target_shard).await? {
+        for room_object in room_objects.objects {
+            let (structure, store) = match &room_object {
+                RoomObject::Storage(storage) => ("storage", &storage.store),
+                RoomObject::Terminal(terminal) => ("terminal", &terminal.store),
+                RoomObject::Factory(factory) => ("factory", &factory.store),
+                _ => {
+                    continue;
+                }
+            };
+            for (resource_type, amount) in store.iter() {
+                rows.push(ResRow {
+                    shard: room_objects.shard.clone(),
+                    room: room_objects.room.clone(),
+                    structure: structure.to_string(),
+                    resource: resource_type.to_string(),
+                    amount: amount.unwrap_or(0),
+                });
+            }
+        }
+    }
+
+    Ok(rows)
+}
+
+/// 查询玩家指定shard具有的资源
+/// 参数：
+/// - username: 玩家名称
+/// - target_shard: 目标 shard，传 `all` 表示所有 shard
+pub async fn query_res(
+    api: &ScreepsApi,
+    username: &str,
+    target_shard: &str,
+) -> ScreepsResult<ShardRes> {
+    let mut result: ShardRes = HashMap::new();
+
+    for row in query_res_rows(api, username, target_shard).await? {
+        result
+            .entry(row.shard)
+            .or_default()
+            .entry(row.resource)
+            .and_modify(|a| *a += row.amount)
+            .or_insert(row.amount);
+    }
+
+    // 限制了公开范围的玩家不在指标中公开资源
+    if privacy::is_public(username) {
+        METRICS.record_resources(username, &result);
+    }
+    live::publish(username, &result);
+    if history::is_enabled() {
+        for (shard, res) in &result {
+            if let Err(e) = history::append("res", &history::player_key(username, shard), res) {
+                eprintln!("Failed to save res history for {}: {}", username, e);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// 资源查询缓存时间，由环境变量 `RES_CACHE_TTL` 控制（秒），默认 0 即不缓存
+pub fn res_cache_ttl() -> Duration {
+    let secs = std::env::var("RES_CACHE_TTL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    Duration::from_secs(secs)
+}
+
+/// 带缓存的资源查询，缓存未过期时直接返回上次的结果
+/// 玩家设置了数据延迟时仍查询最新的数据写入历史记录，返回延迟之前的历史数据
+pub async fn query_res_cached(
+    api: &ScreepsApi,
+    username: &str,
+    target_shard: &str,
+) -> ScreepsResult<ShardRes> {
+    check_shard(target_shard)?;
+    let res = query_res_latest(api, username, target_shard).await?;
+    match privacy::delay(username) {
+        Some(delay) => privacy::delayed_res(username, target_shard, delay),
+        None => Ok(res),
+    }
+}
+
+/// 带缓存的最新资源查询，未开启缓存时直接查询
+async fn query_res_latest(
+    api: &ScreepsApi,
+    username: &str,
+    target_shard: &str,
+) -> ScreepsResult<ShardRes> {
+    let ttl = res_cache_ttl();
+    if ttl.is_zero() {
+        return query_res(api, username, target_shard).await;
+    }
+    let key = (username.to_string(), target_shard.to_string());
+    if let Some((time, res)) = RES_CACHE.lock().unwrap().get(&key)
+        && time.elapsed() < ttl
+    {
+        METRICS.record_cache(true);
+        record_cache_age(time.elapsed());
+        return Ok(res.clone());
+    }
+    METRICS.record_cache(false);
+
+    let res = query_res(api, username, target_shard).await?;
+    let mut cache = RES_CACHE.lock().unwrap();
+    // 先清理过期的条目，仍然超出上限时移除最旧的条目
+    cache.retain(|_, (time, _)| time.elapsed() < ttl);
+    if cache.len() >= RES_CACHE_SIZE
+        && let Some(oldest) = cache
+            .iter()
+            .min_by_key(|(_, (time, _))| *time)
+            .map(|(key, _)| key.clone())
+    {
+        cache.remove(&oldest);
+    }
+    cache.insert(key, (Instant::now(), res.clone()));
+    Ok(res)
+}
+
 /// 绘制资源数据为图片
 pub async fn draw_res_image(
     api: &ScreepsApi,
     username: &str,
     target_shard: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let res = query_res(api, username, target_shard).await?;
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let res = query_res_cached(api, username, target_shard).await?;
+    let _timer = RenderTimer::new("res");
     let res = merge_res(&res);
-    let image_path = format!("data/{}_{}.png", username, target_shard);
     let gap = 100;
     let res_color_map = res_color_map();
-    let root = BitMapBackend::new(&image_path, (9 * gap + 30, 540)).into_drawing_area();
-    root.fill(&parse_color("#2b2b2b").unwrap())?;
-    draw_res_text(&root, "baseRes", 10, 15, "#ffffff");
-    BASE_RES.iter().enumerate().for_each(|(i, &name)| {
-        draw_res(
-            &root,
-            &res_color_map,
-            name,
-            res.get(name).unwrap_or(&0),
-            30 + gap * (i as u32),
-            30,
-        );
-    });
-
-    draw_res_text(&root, "barsRes", 10, 65, "#ffffff");
-    BARS_RES.iter().enumerate().for_each(|(i, &name)| {
-        draw_res(
-            &root,
-            &res_color_map,
-            name,
-            res.get(name).unwrap_or(&0),
-            30 + gap * (i as u32),
-            80,
-        );
-    });
-
-    draw_res_text(&root, "powerRes", 10, 115, "#ffffff");
-    POWER_RES.iter().enumerate().for_each(|(i, &name)| {
-        draw_res(
-            &root,
-            &res_color_map,
-            name,
-            res.get(name).unwrap_or(&0),
-            30 + gap * (i as u32),
-            130,
-        );
-    });
-
-    draw_res_text(&root, "goods", 10, 165, "#ffffff");
-    let goods: Vec<Box<[&str]>> = vec![
-        Box::new(C_GREY_RES),
-        Box::new(C_BLUE_RES),
-        Box::new(C_YELLOW_RES),
-        Box::new(C_PINK_RES),
-        Box::new(C_GREEN_RES),
-    ];
-    for (y, goods) in goods.iter().enumerate() {
-        goods.iter().enumerate().for_each(|(i, &name)| {
+    render_png((9 * gap + 30, 540), |root| {
+        root.fill(&parse_color("#2b2b2b").unwrap())?;
+        draw_res_text(root, "baseRes", 10, 15, "#ffffff");
+        BASE_RES.iter().enumerate().for_each(|(i, &name)| {
             draw_res(
-                &root,
+                root,
                 &res_color_map,
                 name,
                 res.get(name).unwrap_or(&0),
                 30 + gap * (i as u32),
-                180 + (y as u32) * 30,
+                30,
             );
         });
-    }
 
-    draw_res_text(&root, "labRes", 10, 335, "#ffffff");
-    let goods: Vec<Box<[&str]>> = vec![
-        Box::new(B_GREY_RES),
-        Box::new(B_BLUE_RES),
-        Box::new(B_YELLOW_RES),
-        Box::new(B_PINK_RES),
-        Box::new(B_GREEN_RES),
-        Box::new(B_WHITE_RES),
-    ];
-    for (y, goods) in goods.iter().enumerate() {
-        goods.iter().enumerate().for_each(|(i, &name)| {
+        draw_res_text(root, "barsRes", 10, 65, "#ffffff");
+        BARS_RES.iter().enumerate().for_each(|(i, &name)| {
             draw_res(
-                &root,
+                root,
                 &res_color_map,
                 name,
                 res.get(name).unwrap_or(&0),
                 30 + gap * (i as u32),
-                350 + (y as u32) * 30,
+                80,
             );
         });
-    }
 
-    // 当前时间
-    let now: DateTime<Local> = Local::now();
-    let time_str = now.format("%Y/%m/%d %H:%M:%S").to_string();
-    draw_res_text(&root, &time_str, 780, 400, "#888");
+        draw_res_text(root, "powerRes", 10, 115, "#ffffff");
+        POWER_RES.iter().enumerate().for_each(|(i, &name)| {
+            draw_res(
+                root,
+                &res_color_map,
+                name,
+                res.get(name).unwrap_or(&0),
+                30 + gap * (i as u32),
+                130,
+            );
+        });
 
-    let shard = if target_shard == "all" {
-        "all shard"
-    } else {
-        target_shard
-    };
-    let user = format!("{} {}", username, shard);
-    draw_res_text(&root, &user, 780, 420, "#888");
+        draw_res_text(root, "goods", 10, 165, "#ffffff");
+        let goods: Vec<Box<[&str]>> = vec![
+            Box::new(C_GREY_RES),
+            Box::new(C_BLUE_RES),
+            Box::new(C_YELLOW_RES),
+            Box::new(C_PINK_RES),
+            Box::new(C_GREEN_RES),
+        ];
+        for (y, goods) in goods.iter().enumerate() {
+            goods.iter().enumerate().for_each(|(i, &name)| {
+                draw_res(
+                    root,
+                    &res_color_map,
+                    name,
+                    res.get(name).unwrap_or(&0),
+                    30 + gap * (i as u32),
+                    180 + (y as u32) * 30,
+                );
+            });
+        }
 
-    root.present()?;
+        draw_res_text(root, "labRes", 10, 335, "#ffffff");
+        let goods: Vec<Box<[&str]>> = vec![
+            Box::new(B_GREY_RES),
+            Box::new(B_BLUE_RES),
+            Box::new(B_YELLOW_RES),
+            Box::new(B_PINK_RES),
+            Box::new(B_GREEN_RES),
+            Box::new(B_WHITE_RES),
+        ];
+        for (y, goods) in goods.iter().enumerate() {
+            goods.iter().enumerate().for_each(|(i, &name)| {
+                draw_res(
+                    root,
+                    &res_color_map,
+                    name,
+                    res.get(name).unwrap_or(&0),
+                    30 + gap * (i as u32),
+                    350 + (y as u32) * 30,
+                );
+            });
+        }
 
-    Ok(image_path.clone())
+        let shard = if target_shard == "all" {
+            "all shard"
+        } else {
+            target_shard
+        };
+        let user = format!("{} {}", username, shard);
+        draw_res_text(root, &user, 780, 420, "#888");
+        Ok(())
+    })
 }
@@ -0,0 +1,515 @@
+use crate::{
+    constants::{
+        BASE_RES, CONTROLLER_DOWNGRADE_SAFEMODE_THRESHOLD, CONTROLLER_LEVELS, CONTROLLER_STRUCTURES,
+    },
+    history::{self, HistoryRecord},
+    res::{query_game_times, query_room_objects},
+};
+use screeps_rust_api::{RoomObject, ScreepsApi, ScreepsResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// 历史记录中的控制器进度
+#[derive(Serialize, Deserialize)]
+pub struct ControllerSnapshot {
+    pub level: u32,
+    pub progress: u64,
+}
+
+/// 房间控制器信息
+#[derive(Serialize, ToSchema)]
+pub struct ControllerInfo {
+    pub shard: String,
+    pub room: String,
+    pub level: u32,
+    pub progress: u64,
+    /// 升到下一级所需进度，8 级时为 `None`
+    pub progress_total: Option<u64>,
+    pub ticks_to_downgrade: Option<u64>,
+    /// 降级计时过低，有降级风险
+    pub downgrade_risk: bool,
+    pub safe_mode_available: u32,
+    pub safe_mode_cooldown: Option<u64>,
+    /// 安全模式剩余 tick，未开启时为 `None`
+    pub safe_mode: Option<u64>,
+    /// 预计升到下一级所需秒数，需要开启历史记录
+    pub seconds_to_next_level: Option<f64>,
+}
+
+/// 控制器历史记录间隔，由环境变量 `CONTROLLER_HISTORY_INTERVAL` 控制（秒），默认 600
+fn controller_history_interval() -> i64 {
+    std::env::var("CONTROLLER_HISTORY_INTERVAL")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(600)
+}
+
+/// 是否需要记录控制器进度，同一房间在间隔内只记录一条，等级变化时立即记录
+fn should_record(
+    records: &[HistoryRecord<ControllerSnapshot>],
+    current: &ControllerSnapshot,
+    now: i64,
+    interval: i64,
+) -> bool {
+    records
+        .last()
+        .is_none_or(|last| last.value.level != current.level || now - last.time >= interval)
+}
+
+/// 查询玩家各房间的控制器信息
+/// 参数：
+/// - username: 玩家名称
+/// - target_shard: 目标 shard，传 `all` 表示所有 shard
+pub async fn query_controllers(
+    api: &ScreepsApi,
+    username: &str,
+    target_shard: &str,
+) -> ScreepsResult<Vec<ControllerInfo>> {
+    let room_objects_list = query_room_objects(api, username, target_shard).await?;
+
+    let game_times = query_game_times(api, &room_objects_list).await?;
+
+    let mut result = Vec::new();
+    for room_objects in room_objects_list {
+        let game_time = game_times[&room_objects.shard];
+        for room_object in room_objects.objects {
+            let RoomObject::Controller(controller) = room_object else {
+                continue;
+            };
+            let level = controller.level.unwrap_or(0);
+            let progress = controller.progress.unwrap_or(0);
+            let progress_total = CONTROLLER_LEVELS
+                .get(level as usize)
+                .copied()
+                .filter(|t| *t > 0);
+            let ticks_to_downgrade = controller
+                .downgrade_time
+                .map(|t| t.saturating_sub(game_time));
+
+            let mut seconds_to_next_level = None;
+            if history::is_enabled() {
+                let key = format!("{}_{}", room_objects.shard, room_objects.room);
+                let snapshot = ControllerSnapshot { level, progress };
+                let records = history::load::<ControllerSnapshot>("controller", &key, None, None)
+                    .unwrap_or_default();
+                let now = chrono::Utc::now().timestamp();
+                if let Some(total) = progress_total {
+                    seconds_to_next_level =
+                        estimate_seconds_to_next_level(&records, &snapshot, total, now);
+                }
+                if should_record(&records, &snapshot, now, controller_history_interval())
+                    && let Err(e) = history::append("controller", &key, &snapshot)
+                {
+                    eprintln!("Failed to save controller history for {}: {}", key, e);
+                }
+            }
+
+            result.push(ControllerInfo {
+                shard: room_objects.shard.clone(),
+                room: room_objects.room.clone(),
+                level,
+                progress,
+                progress_total,
+                ticks_to_downgrade,
+                downgrade_risk: level > 0
+                    && ticks_to_downgrade
+                        .is_some_and(|t| t < CONTROLLER_DOWNGRADE_SAFEMODE_THRESHOLD),
+                safe_mode_available: controller.safe_mode_available.unwrap_or(0),
+                safe_mode_cooldown: controller
+                    .safe_mode_cooldown
+                    .map(|t| t.saturating_sub(game_time))
+                    .filter(|t| *t > 0),
+                safe_mode: controller
+                    .safe_mode
+                    .map(|t| t.saturating_sub(game_time))
+                    .filter(|t| *t > 0),
+                seconds_to_next_level,
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+/// 根据历史进度估算升到下一级所需秒数
+/// 取最近一段连续处于当前等级的记录中最早的一条计算平均升级速度
+fn estimate_seconds_to_next_level(
+    records: &[HistoryRecord<ControllerSnapshot>],
+    current: &ControllerSnapshot,
+    progress_total: u64,
+    now: i64,
+) -> Option<f64> {
+    let first = records
+        .iter()
+        .rev()
+        .take_while(|record| record.value.level == current.level)
+        .last()?;
+    let elapsed = (now - first.time) as f64;
+    let gained = current.progress.checked_sub(first.value.progress)? as f64;
+    if elapsed <= 0.0 || gained <= 0.0 {
+        return None;
+    }
+    let remaining = progress_total.saturating_sub(current.progress) as f64;
+    Some(remaining / (gained / elapsed))
+}
+
+/// 建筑数量与当前等级允许的最大数量
+#[derive(Serialize, ToSchema)]
+pub struct StructureCount {
+    pub count: u32,
+    pub max: u32,
+}
+
+/// 房间建筑统计
+#[derive(Serialize, ToSchema)]
+pub struct RoomStructures {
+    pub shard: String,
+    pub room: String,
+    pub level: u32,
+    pub structures: HashMap<String, StructureCount>,
+    /// 还可以建造的建筑数量
+    pub missing: HashMap<String, u32>,
+    /// 血量低于阈值的 rampart 数量
+    pub damaged_ramparts: u32,
+    /// 血量低于阈值的 wall 数量
+    pub damaged_walls: u32,
+    /// 所有建筑是否已建满
+    pub complete: bool,
+}
+
+/// 获取房间对象对应的建筑类型
+fn structure_type(room_object: &RoomObject) -> Option<&'static str> {
+    match room_object {
+        RoomObject::Spawn(_) => Some("spawn"),
+        RoomObject::Extension(_) => Some("extension"),
+        RoomObject::Lab(_) => Some("lab"),
+        RoomObject::Tower(_) => Some("tower"),
+        RoomObject::Link(_) => Some("link"),
+        RoomObject::Factory(_) => Some("factory"),
+        RoomObject::Nuker(_) => Some("nuker"),
+        RoomObject::Observer(_) => Some("observer"),
+        RoomObject::PowerSpawn(_) => Some("powerSpawn"),
+        _ => None,
+    }
+}
+
+/// 按控制器等级统计建筑数量上限和还可以建造的数量
+/// 返回 (建筑数量统计, 还可以建造的建筑数量)
+fn count_structures(
+    counts: &HashMap<&str, u32>,
+    level: u32,
+) -> (HashMap<String, StructureCount>, HashMap<String, u32>) {
+    let mut structures = HashMap::new();
+    let mut missing = HashMap::new();
+    for (name, limits) in CONTROLLER_STRUCTURES.iter() {
+        let count = counts.get(name).copied().unwrap_or(0);
+        let max = limits[(level as usize).min(8)];
+        if count < max {
+            missing.insert(name.to_string(), max - count);
+        }
+        structures.insert(name.to_string(), StructureCount { count, max });
+    }
+    (structures, missing)
+}
+
+/// 查询玩家各房间的建筑完成度
+/// 参数：
+/// - username: 玩家名称
+/// - target_shard: 目标 shard，传 `all` 表示所有 shard
+/// - min_hits: rampart 和 wall 血量低于该值视为受损
+pub async fn query_structures(
+    api: &ScreepsApi,
+    username: &str,
+    target_shard: &str,
+    min_hits: u32,
+) -> ScreepsResult<Vec<RoomStructures>> {
+    let mut result = Vec::new();
+    for room_objects in query_room_objects(api, username, target_shard).await? {
+        let mut level = 0;
+        let mut counts: HashMap<&str, u32> = HashMap::new();
+        let mut damaged_ramparts = 0;
+        let mut damaged_walls = 0;
+        for room_object in &room_objects.objects {
+            if let Some(name) = structure_type(room_object) {
+                *counts.entry(name).or_insert(0) += 1;
+                continue;
+            }
+            match room_object {
+                RoomObject::Controller(controller) => level = controller.level.unwrap_or(0),
+                RoomObject::Rampart(rampart) if rampart.hits.unwrap_or(0) < min_hits => {
+                    damaged_ramparts += 1;
+                }
+                RoomObject::ConstructedWall(wall) if wall.hits.unwrap_or(0) < min_hits => {
+                    damaged_walls += 1;
+                }
+                _ => {}
+            }
+        }
+
+        let (structures, missing) = count_structures(&counts, level);
+        result.push(RoomStructures {
+            shard: room_objects.shard,
+            room: room_objects.room,
+            level,
+            structures,
+            complete: missing.is_empty(),
+            missing,
+            damaged_ramparts,
+            damaged_walls,
+        });
+    }
+
+    Ok(result)
+}
+
+/// 房间矿物信息
+#[derive(Serialize, ToSchema)]
+pub struct RoomMineral {
+    pub shard: String,
+    pub room: String,
+    pub mineral_type: String,
+    pub density: u32,
+    pub amount: u32,
+    /// 距离矿物再生的 tick，矿物未采完时为 `None`
+    pub regeneration: Option<u64>,
+    pub has_extractor: bool,
+    /// 房间中 source 的数量
+    pub sources: u32,
+}
+
+/// 基础矿物来源统计
+#[derive(Serialize, Default, ToSchema)]
+pub struct MineralSummary {
+    /// 可以自己采集或合成的基础资源
+    pub native: Vec<String>,
+    /// 拥有矿物但没有建造 extractor
+    pub no_extractor: Vec<String>,
+    /// 需要购买的基础资源
+    pub must_buy: Vec<String>,
+}
+
+/// 玩家矿物概况
+#[derive(Serialize, ToSchema)]
+pub struct MineralsOverview {
+    pub rooms: Vec<RoomMineral>,
+    pub summary: MineralSummary,
+}
+
+/// 查询玩家各房间的矿物信息
+/// 参数：
+/// - username: 玩家名称
+/// - target_shard: 目标 shard，传 `all` 表示所有 shard
+pub async fn query_minerals(
+    api: &ScreepsApi,
+    username: &str,
+    target_shard: &str,
+) -> ScreepsResult<MineralsOverview> {
+    let room_objects_list = query_room_objects(api, username, target_shard).await?;
+    let game_times = query_game_times(api, &room_objects_list).await?;
+
+    let mut rooms = Vec::new();
+    for room_objects in room_objects_list {
+        let game_time = game_times[&room_objects.shard];
+        let mut mineral = None;
+        let mut has_extractor = false;
+        let mut sources = 0;
+        for room_object in room_objects.objects {
+            match room_object {
+                RoomObject::Mineral(m) => mineral = Some(m),
+                RoomObject::Extractor(_) => has_extractor = true,
+                RoomObject::Source(_) => sources += 1,
+                _ => {}
+            }
+        }
+        let Some(mineral) = mineral else {
+            continue;
+        };
+        rooms.push(RoomMineral {
+            shard: room_objects.shard,
+            room: room_objects.room,
+            mineral_type: mineral.mineral_type.to_string(),
+            density: mineral.density.unwrap_or(0),
+            amount: mineral.mineral_amount.unwrap_or(0),
+            regeneration: mineral
+                .next_regeneration_time
+                .map(|t| t.saturating_sub(game_time))
+                .filter(|t| *t > 0),
+            has_extractor,
+            sources,
+        });
+    }
+
+    let summary = summarize_minerals(&rooms);
+    Ok(MineralsOverview { rooms, summary })
+}
+
+/// 统计哪些基础资源可以自己生产，哪些需要购买
+/// 能量总是可以自己采集，G 需要 U、L、Z、K 都能自己生产
+fn summarize_minerals(rooms: &[RoomMineral]) -> MineralSummary {
+    let harvested = |res: &str| {
+        rooms
+            .iter()
+            .any(|room| room.mineral_type == res && room.has_extractor)
+    };
+    let mut summary = MineralSummary::default();
+    for res in BASE_RES {
+        let native = match res {
+            "energy" => true,
+            "G" => ["U", "L", "Z", "K"].iter().all(|r| harvested(*r)),
+            _ => harvested(res),
+        };
+        if native {
+            summary.native.push(res.to_string());
+        } else {
+            if rooms.iter().any(|room| room.mineral_type == res) {
+                summary.no_extractor.push(res.to_string());
+            }
+            summary.must_buy.push(res.to_string());
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_seconds_to_next_level() {
+        let records = vec![
+            HistoryRecord {
+                time: 0,
+                value: ControllerSnapshot {
+                    level: 3,
+                    progress: 100000,
+                },
+            },
+            HistoryRecord {
+                time: 100,
+                value: ControllerSnapshot {
+                    level: 4,
+                    progress: 0,
+                },
+            },
+        ];
+        let current = ControllerSnapshot {
+            level: 4,
+            progress: 1000,
+        };
+        // 100 秒升级 1000 点，还剩 404000 点
+        assert_eq!(
+            estimate_seconds_to_next_level(&records, &current, 405000, 200),
+            Some(40400.0)
+        );
+        // 没有进度变化时无法估算
+        let current = ControllerSnapshot {
+            level: 4,
+            progress: 0,
+        };
+        assert_eq!(
+            estimate_seconds_to_next_level(&records, &current, 405000, 200),
+            None
+        );
+
+        // 降级后再次升级，使用最近一次升到当前等级之后的记录
+        let records = vec![
+            HistoryRecord {
+                time: 0,
+                value: ControllerSnapshot {
+                    level: 4,
+                    progress: 0,
+                },
+            },
+            HistoryRecord {
+                time: 100,
+                value: ControllerSnapshot {
+                    level: 3,
+                    progress: 0,
+                },
+            },
+            HistoryRecord {
+                time: 150,
+                value: ControllerSnapshot {
+                    level: 4,
+                    progress: 500,
+                },
+            },
+        ];
+        let current = ControllerSnapshot {
+            level: 4,
+            progress: 1000,
+        };
+        assert_eq!(
+            estimate_seconds_to_next_level(&records, &current, 405000, 200),
+            Some(40400.0)
+        );
+    }
+
+    #[test]
+    fn test_should_record() {
+        let records = vec![HistoryRecord {
+            time: 0,
+            value: ControllerSnapshot {
+                level: 4,
+                progress: 0,
+            },
+        }];
+        let current = ControllerSnapshot {
+            level: 4,
+            progress: 100,
+        };
+        assert!(should_record(&[], &current, 0, 600));
+        assert!(!should_record(&records, &current, 599, 600));
+        assert!(should_record(&records, &current, 600, 600));
+        let current = ControllerSnapshot {
+            level: 5,
+            progress: 0,
+        };
+        assert!(should_record(&records, &current, 10, 600));
+    }
+
+    #[test]
+    fn test_count_structures() {
+        let counts = HashMap::from([("spawn", 1), ("extension", 3)]);
+        let (structures, missing) = count_structures(&counts, 2);
+        assert_eq!(structures["extension"].max, 5);
+        assert_eq!(structures["tower"].max, 0);
+        assert_eq!(missing, HashMap::from([("extension".to_string(), 2)]));
+
+        let counts = HashMap::from([("spawn", 3), ("extension", 60), ("lab", 10), ("tower", 6)]);
+        let (structures, missing) = count_structures(&counts, 8);
+        assert_eq!(structures["extension"].max, 60);
+        assert_eq!(structures["spawn"].max, 3);
+        assert_eq!(structures["link"].max, 6);
+        assert_eq!(
+            missing,
+            HashMap::from([
+                ("link".to_string(), 6),
+                ("factory".to_string(), 1),
+                ("nuker".to_string(), 1),
+                ("observer".to_string(), 1),
+                ("powerSpawn".to_string(), 1),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_summarize_minerals() {
+        let room = |mineral_type: &str, has_extractor: bool| RoomMineral {
+            shard: "shard3".to_string(),
+            room: "W1N1".to_string(),
+            mineral_type: mineral_type.to_string(),
+            density: 3,
+            amount: 0,
+            regeneration: None,
+            has_extractor,
+            sources: 2,
+        };
+        let rooms = vec![room("H", true), room("O", false), room("U", true)];
+        let summary = summarize_minerals(&rooms);
+        assert_eq!(summary.native, vec!["energy", "U", "H"]);
+        assert_eq!(summary.no_extractor, vec!["O"]);
+        assert_eq!(summary.must_buy, vec!["L", "K", "Z", "X", "O", "G"]);
+    }
+}
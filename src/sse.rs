@@ -0,0 +1,147 @@
+use crate::{
+    live::{self, RefreshError, ResUpdate},
+    ratelimit,
+};
+use axum::response::sse::Event;
+use futures::Stream;
+use screeps_rust_api::ScreepsApi;
+use serde_json::json;
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    sync::Arc,
+};
+use tokio::{
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
+    time::Interval,
+};
+
+/// 资源事件流的状态
+struct StreamState {
+    api: Arc<ScreepsApi>,
+    /// 限流的客户端，后台查询的消耗计入该客户端
+    client: Option<ratelimit::Client>,
+    updates: broadcast::Receiver<Arc<ResUpdate>>,
+    /// 后台查询的错误
+    errors: mpsc::UnboundedReceiver<RefreshError>,
+    errors_tx: mpsc::UnboundedSender<RefreshError>,
+    /// 轮询订阅主题的定时器
+    poll: Option<Interval>,
+    /// 订阅的主题 (玩家名称, shard)
+    topic: (String, String),
+    threshold: i32,
+    /// 每个 shard 已推送给客户端的资源数量，作为判断变化的基准
+    baseline: HashMap<String, HashMap<String, i32>>,
+    /// 等待发送的事件
+    pending: VecDeque<Event>,
+}
+
+/// 资源快照事件
+fn snapshot_event(username: &str, shard: &str, res: &HashMap<String, i32>) -> Event {
+    Event::default()
+        .event("snapshot")
+        .data(json!({ "username": username, "shard": shard, "res": res }).to_string())
+}
+
+/// 玩家资源的事件流
+/// 先推送已有的资源快照（`snapshot` 事件），之后资源数量相对上次推送的变化量超过 threshold 时
+/// 推送发生变化的资源（`change` 事件），消失的资源数量为 0
+/// 订阅的主题每隔 `LIVE_POLL_INTERVAL` 秒查询一次，查询失败时推送 `error` 事件
+pub fn res_stream(
+    api: Arc<ScreepsApi>,
+    username: String,
+    shard: String,
+    threshold: i32,
+    client: Option<ratelimit::Client>,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    // 先订阅再读取快照，避免漏掉中间的更新
+    let updates = live::subscribe();
+    let snapshots = live::snapshots(&username, &shard);
+    let (errors_tx, errors) = mpsc::unbounded_channel();
+    if snapshots.is_empty() {
+        live::request_refresh(
+            api.clone(),
+            username.clone(),
+            shard.clone(),
+            client.clone(),
+            errors_tx.clone(),
+        );
+    }
+    let pending = snapshots
+        .iter()
+        .map(|(s, res)| snapshot_event(&username, s, res))
+        .collect();
+    let state = StreamState {
+        api,
+        client,
+        updates,
+        errors,
+        errors_tx,
+        poll: live::poll_timer(),
+        topic: (username, shard),
+        threshold,
+        baseline: snapshots.into_iter().collect(),
+        pending,
+    };
+
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((Ok(event), state));
+            }
+            let update = tokio::select! {
+                _ = live::next_poll(&mut state.poll) => {
+                    let (username, shard) = state.topic.clone();
+                    live::request_refresh(
+                        state.api.clone(),
+                        username,
+                        shard,
+                        state.client.clone(),
+                        state.errors_tx.clone(),
+                    );
+                    continue;
+                }
+                Some(error) = state.errors.recv() => {
+                    let event = Event::default().event("error").data(
+                        json!({ "username": error.username, "shard": error.shard, "message": error.message })
+                            .to_string(),
+                    );
+                    return Some((Ok(event), state));
+                }
+                update = state.updates.recv() => update,
+            };
+            let update = match update {
+                Ok(update) => update,
+                // 丢失的更新不影响结果，下次更新会带上最新的数据
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            };
+            if !live::topic_matches(&state.topic, &update) {
+                continue;
+            }
+            let Some(baseline) = state.baseline.get_mut(&update.shard) else {
+                state
+                    .baseline
+                    .insert(update.shard.clone(), update.res.clone());
+                let event = snapshot_event(&update.username, &update.shard, &update.res);
+                return Some((Ok(event), state));
+            };
+            let changes = live::diff(baseline, &update.res, state.threshold);
+            if changes.is_empty() {
+                continue;
+            }
+            // 只更新推送了的资源，小的变化会累积到超过阈值后推送
+            for (resource, amount) in &changes {
+                baseline.insert(resource.clone(), *amount);
+            }
+            let event = Event::default().event("change").data(
+                json!({ "username": update.username, "shard": update.shard, "changes": changes })
+                    .to_string(),
+            );
+            return Some((Ok(event), state));
+        }
+    })
+}
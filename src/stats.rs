@@ -0,0 +1,145 @@
+use crate::{
+    config::{Config, StatsTarget},
+    history::{self, HistoryRecord},
+    memory::{flatten_numbers, query_stats},
+    metrics::RenderTimer,
+    res::check_shard,
+    utils::{draw_line_chart, parse_color, render_png},
+};
+use screeps_rust_api::{ScreepsError, ScreepsResult};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+
+/// 折线图最多绘制的字段数
+const MAX_CHART_FIELDS: usize = 6;
+
+/// 一次采集的统计数据，键为展开后的字段名，如 `cpu.used`
+pub type StatsValues = BTreeMap<String, f64>;
+
+/// 采集一个玩家的统计数据，展开数值字段后写入历史记录
+async fn collect_stats(client: &reqwest::Client, target: &StatsTarget) {
+    let stats = match query_stats(client, target).await {
+        Ok(stats) => stats,
+        Err(e) => {
+            eprintln!("Failed to read stats of {}: {}", target.username, e);
+            return;
+        }
+    };
+    let values = flatten_numbers(&stats);
+    if values.is_empty() {
+        return;
+    }
+    let key = history::player_key(&target.username, &target.shard);
+    if let Err(e) = history::append("stats", &key, &values) {
+        eprintln!(
+            "Failed to save stats history for {}: {}",
+            target.username, e
+        );
+    }
+}
+
+/// 启动统计数据采集任务，没有配置采集目标或没有开启历史记录时不启动
+pub fn spawn_stats_job(config: Arc<Config>) -> Option<JoinHandle<()>> {
+    if config.stats.targets.is_empty() {
+        return None;
+    }
+    if !history::is_enabled() {
+        eprintln!("Stats collection is configured but HISTORY_ENABLED is off");
+        return None;
+    }
+    Some(tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut interval = tokio::time::interval(Duration::from_secs(config.stats.interval));
+        loop {
+            interval.tick().await;
+            for target in &config.stats.targets {
+                collect_stats(&client, target).await;
+            }
+        }
+    }))
+}
+
+/// 解析逗号分隔的字段列表，空字符串表示所有字段
+pub fn parse_fields(fields: Option<&str>) -> Vec<String> {
+    fields
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// 查询玩家的统计数据历史
+/// 参数：
+/// - fields: 需要的字段，为空时返回所有字段
+/// - since: 起始 unix 时间戳（秒），`None` 表示不限制
+/// - until: 结束 unix 时间戳（秒），`None` 表示不限制
+pub fn query_stats_history(
+    username: &str,
+    shard: &str,
+    fields: &[String],
+    since: Option<i64>,
+    until: Option<i64>,
+) -> ScreepsResult<Vec<HistoryRecord<StatsValues>>> {
+    check_shard(shard)?;
+    let mut records: Vec<HistoryRecord<StatsValues>> =
+        history::load("stats", &history::player_key(username, shard), since, until)
+            .map_err(|e| ScreepsError::Api(format!("读取历史记录失败: {}", e)))?;
+    if !fields.is_empty() {
+        for record in &mut records {
+            record.value.retain(|field, _| fields.contains(field));
+        }
+    }
+    Ok(records)
+}
+
+/// 绘制统计数据折线图，每个字段一张，未指定字段时绘制最新记录中的前几个字段，返回 PNG 图片
+pub fn draw_stats_image(
+    username: &str,
+    shard: &str,
+    fields: &[String],
+    since: Option<i64>,
+    until: Option<i64>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let records = query_stats_history(username, shard, fields, since, until)?;
+    let _timer = RenderTimer::new("stats");
+    let fields: Vec<String> = if fields.is_empty() {
+        records
+            .last()
+            .map(|r| r.value.keys().cloned().collect())
+            .unwrap_or_default()
+    } else {
+        fields.to_vec()
+    };
+    let fields = &fields[..fields.len().min(MAX_CHART_FIELDS)];
+
+    let height = 200 * fields.len().max(1) as u32;
+    render_png((800, height), |root| {
+        root.fill(&parse_color("#2b2b2b").unwrap())?;
+        let panels = root.split_evenly((fields.len().max(1), 1));
+        for (panel, field) in panels.iter().zip(fields) {
+            let points: Vec<(i64, f64)> = records
+                .iter()
+                .filter_map(|r| r.value.get(field).map(|v| (r.time, *v)))
+                .collect();
+            draw_line_chart(panel, field, &points, "rgb(108, 240, 169)");
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fields() {
+        assert_eq!(
+            parse_fields(Some("cpu.used, gcl.level,,")),
+            vec!["cpu.used".to_string(), "gcl.level".to_string()]
+        );
+        assert!(parse_fields(Some("")).is_empty());
+        assert!(parse_fields(None).is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{
+    auth,
+    config::Config,
+    error::{ErrorCode, error},
+    ingest,
+    memory::request_api,
+    metrics::track_upstream,
+};
+use aes_gcm::{
+    Aes256Gcm, Nonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+};
+use axum::http::HeaderMap;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use screeps_rust_api::{ScreepsApi, ScreepsError, ScreepsResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{Arc, LazyLock, Mutex},
+};
+use utoipa::ToSchema;
+
+/// 玩家 token 的保存路径
+const TOKENS_PATH: &str = "data/tokens.json";
+
+/// 读取玩家私有数据时，调用者提供玩家 token 的请求头
+pub const TOKEN_HEADER: &str = "x-screeps-token";
+
+/// 加密保存的玩家 token
+#[derive(Serialize, Deserialize)]
+struct StoredToken {
+    username: String,
+    /// base64 编码的 nonce
+    nonce: String,
+    /// base64 编码的加密后的 token
+    token: String,
+    updated_at: i64,
+}
+
+/// 已注册的玩家 token，键为小写的玩家名称
+static TOKENS: LazyLock<Mutex<HashMap<String, StoredToken>>> = LazyLock::new(|| {
+    let tokens = fs::read_to_string(TOKENS_PATH)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    Mutex::new(tokens)
+});
+
+/// 玩家注册 token 的请求
+#[derive(Deserialize, ToSchema)]
+pub struct TokenRegistration {
+    /// 玩家的 Screeps API token
+    pub token: String,
+}
+
+/// 已注册 token 的玩家
+#[derive(Serialize, ToSchema)]
+pub struct TokenInfo {
+    pub username: String,
+    /// 注册时间，unix 时间戳（秒）
+    pub updated_at: i64,
+}
+
+/// token 所属玩家的信息
+pub struct TokenUser {
+    pub username: String,
+    pub credits: f64,
+}
+
+/// Screeps 玩家名称不区分大小写
+fn normalize(username: &str) -> String {
+    username.to_lowercase()
+}
+
+/// 加密 token 使用的密钥，由环境变量 `TOKEN_SECRET` 提供，为 base64 编码的 32 字节
+fn cipher() -> ScreepsResult<Aes256Gcm> {
+    let secret = std::env::var("TOKEN_SECRET")
+        .map_err(|_| error(ErrorCode::Unavailable, "未配置 TOKEN_SECRET"))?;
+    let key = STANDARD
+        .decode(secret.trim())
+        .map_err(|e| ScreepsError::Api(format!("TOKEN_SECRET 格式错误: {}", e)))?;
+    Aes256Gcm::new_from_slice(&key)
+        .map_err(|_| ScreepsError::Api("TOKEN_SECRET 长度必须为 32 字节".to_string()))
+}
+
+/// 加密 token，返回 base64 编码的 (nonce, 密文)
+fn encrypt(cipher: &Aes256Gcm, token: &str) -> ScreepsResult<(String, String)> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let encrypted = cipher
+        .encrypt(&nonce, token.as_bytes())
+        .map_err(|_| ScreepsError::Api("加密 token 失败".to_string()))?;
+    Ok((STANDARD.encode(nonce), STANDARD.encode(encrypted)))
+}
+
+/// 解密保存的 token
+fn decrypt(cipher: &Aes256Gcm, stored: &StoredToken) -> Option<String> {
+    let nonce = STANDARD.decode(&stored.nonce).ok()?;
+    if nonce.len() != 12 {
+        return None;
+    }
+    let encrypted = STANDARD.decode(&stored.token).ok()?;
+    let token = cipher
+        .decrypt(Nonce::from_slice(&nonce), encrypted.as_slice())
+        .ok()?;
+    String::from_utf8(token).ok()
+}
+
+/// 保存所有玩家的 token
+fn save(tokens: &HashMap<String, StoredToken>) -> std::io::Result<()> {
+    if let Some(dir) = Path::new(TOKENS_PATH).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(TOKENS_PATH, serde_json::to_string_pretty(tokens)?)
+}
+
+/// 查询 token 所属的玩家
+pub async fn query_token_user(client: &reqwest::Client, token: &str) -> ScreepsResult<TokenUser> {
+    let response = track_upstream("auth_me", request_api(client, token, "auth/me", &[])).await?;
+    let username = response["username"]
+        .as_str()
+        .ok_or(error(ErrorCode::Unauthorized, "token 无效"))?;
+    Ok(TokenUser {
+        username: username.to_string(),
+        // 接口返回的 money 单位为千分之一 credit
+        credits: response["money"].as_f64().unwrap_or(0.0) / 1000.0,
+    })
+}
+
+/// 玩家注册自己的 token，验证 token 后加密保存
+/// 开启了 socket 数据接入时使用该 token 接入玩家的 CPU 和控制台
+pub async fn register(
+    api: Arc<ScreepsApi>,
+    config: &Config,
+    token: String,
+) -> ScreepsResult<TokenInfo> {
+    let cipher = cipher()?;
+    let user = query_token_user(&reqwest::Client::new(), &token).await?;
+    let (nonce, encrypted) = encrypt(&cipher, &token)?;
+    let stored = StoredToken {
+        username: user.username.clone(),
+        nonce,
+        token: encrypted,
+        updated_at: chrono::Utc::now().timestamp(),
+    };
+    let info = TokenInfo {
+        username: stored.username.clone(),
+        updated_at: stored.updated_at,
+    };
+    {
+        let mut tokens = TOKENS.lock().unwrap();
+        tokens.insert(normalize(&user.username), stored);
+        save(&tokens).map_err(|e| ScreepsError::Api(format!("保存 token 失败: {}", e)))?;
+    }
+    ingest::start_player_ingest(api, config, user.username, token);
+    Ok(info)
+}
+
+/// 玩家删除自己注册的 token，需要提供该 token 证明身份
+pub async fn unregister(token: String) -> ScreepsResult<TokenInfo> {
+    let user = query_token_user(&reqwest::Client::new(), &token).await?;
+    let removed = {
+        let mut tokens = TOKENS.lock().unwrap();
+        let removed = tokens.remove(&normalize(&user.username));
+        save(&tokens).map_err(|e| ScreepsError::Api(format!("保存 token 失败: {}", e)))?;
+        removed
+    };
+    let Some(removed) = removed else {
+        return Err(error(ErrorCode::NotFound, "玩家未注册 token"));
+    };
+    ingest::stop_player_ingest(&user.username);
+    Ok(TokenInfo {
+        username: removed.username,
+        updated_at: removed.updated_at,
+    })
+}
+
+/// 获取玩家注册的 token，未注册或无法解密时返回 None
+pub fn player_token(username: &str) -> Option<String> {
+    let tokens = TOKENS.lock().unwrap();
+    let stored = tokens.get(&normalize(username))?;
+    decrypt(&cipher().ok()?, stored)
+}
+
+/// 读取请求头中调用者提供的玩家 token
+pub fn request_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// 验证调用者是玩家本人，返回玩家注册的 token，读取玩家私有数据的接口使用
+/// 调用者需要提供与玩家注册的 token 相同的 token，其他客户端不能读取玩家的私有数据
+pub fn owner_token(username: &str, provided: Option<&str>) -> ScreepsResult<String> {
+    let provided = provided.ok_or(error(
+        ErrorCode::Unauthorized,
+        "读取玩家私有数据需要在 X-Screeps-Token 请求头中提供玩家的 token",
+    ))?;
+    let token = player_token(username).ok_or(error(
+        ErrorCode::Forbidden,
+        "玩家未注册 token，无法读取私有数据",
+    ))?;
+    if !auth::key_eq(&token, provided) {
+        return Err(error(
+            ErrorCode::Forbidden,
+            "token 与玩家注册的 token 不一致",
+        ));
+    }
+    Ok(token)
+}
+
+/// 所有可以解密的玩家 token，返回 (玩家名称, token)
+pub fn all_tokens() -> Vec<(String, String)> {
+    let Ok(cipher) = cipher() else {
+        return Vec::new();
+    };
+    TOKENS
+        .lock()
+        .unwrap()
+        .values()
+        .filter_map(|stored| Some((stored.username.clone(), decrypt(&cipher, stored)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt() {
+        let cipher = Aes256Gcm::new_from_slice(&[7; 32]).unwrap();
+        let (nonce, token) = encrypt(&cipher, "secret-token").unwrap();
+        let mut stored = StoredToken {
+            username: "player".to_string(),
+            nonce,
+            token,
+            updated_at: 0,
+        };
+        assert_ne!(stored.token, "secret-token");
+        assert_eq!(decrypt(&cipher, &stored).as_deref(), Some("secret-token"));
+
+        let other = Aes256Gcm::new_from_slice(&[8; 32]).unwrap();
+        assert_eq!(decrypt(&other, &stored), None);
+        stored.nonce = STANDARD.encode([0; 4]);
+        assert_eq!(decrypt(&cipher, &stored), None);
+    }
+}
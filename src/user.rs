@@ -0,0 +1,433 @@
+use crate::{
+    constants::POWER_NAMES,
+    error::{ErrorCode, error},
+    memory::request_api,
+    metrics::{RenderTimer, track_upstream},
+    res::{fetch_room_objects, query_game_time, query_game_times, query_user_rooms},
+    tokens,
+    utils::{
+        draw_bar, draw_res_text, draw_text, format_number, gcl_level, gpl_level, parse_color,
+        render_png,
+    },
+};
+use chrono::prelude::*;
+use plotters::prelude::*;
+use screeps_rust_api::{RoomObject, ScreepsApi, ScreepsResult};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// 等级及升级进度
+#[derive(Serialize, ToSchema)]
+pub struct LevelProgress {
+    pub level: u32,
+    pub progress: f64,
+    pub progress_total: f64,
+}
+
+impl LevelProgress {
+    fn from_tuple((level, progress, progress_total): (u32, f64, f64)) -> Self {
+        LevelProgress {
+            level,
+            progress,
+            progress_total,
+        }
+    }
+}
+
+/// 玩家概览
+#[derive(Serialize, ToSchema)]
+pub struct UserOverview {
+    pub username: String,
+    pub gcl: LevelProgress,
+    pub gpl: LevelProgress,
+    /// 公开接口无法获取玩家的 credits，玩家注册了自己的 token 且请求提供了该 token 时才有
+    pub credits: Option<f64>,
+    pub badge: Option<serde_json::Value>,
+    /// 每个 shard 的房间数量
+    pub room_count: HashMap<String, usize>,
+    /// 每个 shard 下每个房间的控制器等级
+    pub rooms: HashMap<String, HashMap<String, u32>>,
+}
+
+/// 查询玩家概览信息
+/// 参数：
+/// - username: 玩家名称
+/// - owner_token: 调用者提供的玩家 token，与玩家注册的 token 相同时才返回 credits
+pub async fn query_user_overview(
+    api: &ScreepsApi,
+    username: &str,
+    owner_token: Option<&str>,
+) -> ScreepsResult<UserOverview> {
+    let user_info = track_upstream("user_find", api.get_user_info_by_name(username)).await?;
+    if user_info.base_data.ok == None || user_info.base_data.ok.unwrap() != 1 {
+        return Err(error(ErrorCode::NotFound, "玩家不存在"));
+    }
+    let user = user_info.user.unwrap();
+
+    let user_rooms = query_user_rooms(api, &user._id).await?;
+    let room_count = user_rooms
+        .iter()
+        .map(|(shard, rooms)| (shard.clone(), rooms.len()))
+        .collect();
+
+    // 从房间对象中读取控制器等级
+    let mut rooms: HashMap<String, HashMap<String, u32>> = HashMap::new();
+    for room_objects in fetch_room_objects(api, &user_rooms, "all").await? {
+        for room_object in room_objects.objects {
+            if let RoomObject::Controller(controller) = room_object {
+                rooms
+                    .entry(room_objects.shard.clone())
+                    .or_default()
+                    .insert(room_objects.room.clone(), controller.level.unwrap_or(0));
+            }
+        }
+    }
+
+    // 读取 credits 失败不影响其他数据
+    let credits = match tokens::owner_token(username, owner_token).ok() {
+        Some(token) => match tokens::query_token_user(&reqwest::Client::new(), &token).await {
+            Ok(token_user) => Some(token_user.credits),
+            Err(e) => {
+                eprintln!("Failed to query credits for {}: {}", username, e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    Ok(UserOverview {
+        username: user.username.clone(),
+        gcl: LevelProgress::from_tuple(gcl_level(user.gcl as f64)),
+        gpl: LevelProgress::from_tuple(gpl_level(user.power as f64)),
+        credits,
+        badge: serde_json::to_value(&user.badge).ok(),
+        room_count,
+        rooms,
+    })
+}
+
+/// 查询玩家自己的市场订单，需要玩家注册自己的 token
+/// 参数：
+/// - username: 玩家名称
+/// - owner_token: 调用者提供的玩家 token，与玩家注册的 token 相同时才能查询
+pub async fn query_market_orders(
+    username: &str,
+    owner_token: Option<&str>,
+) -> ScreepsResult<Vec<serde_json::Value>> {
+    let token = tokens::owner_token(username, owner_token)?;
+    let client = reqwest::Client::new();
+    let mut response = track_upstream(
+        "market_orders",
+        request_api(&client, &token, "game/market/my-orders", &[]),
+    )
+    .await?;
+    Ok(match response["list"].take() {
+        serde_json::Value::Array(orders) => orders,
+        _ => Vec::new(),
+    })
+}
+
+/// 超能力等级和冷却
+#[derive(Serialize, ToSchema)]
+pub struct PowerStatus {
+    pub id: String,
+    pub name: String,
+    pub level: u32,
+    /// 剩余冷却 tick
+    pub cooldown: u64,
+}
+
+/// 超能 creep 状态
+#[derive(Serialize, ToSchema)]
+pub struct PowerCreepInfo {
+    pub name: String,
+    pub class_name: String,
+    pub level: u32,
+    pub powers: Vec<PowerStatus>,
+    /// 所在 shard，未生成时为 `None`
+    pub shard: Option<String>,
+    /// 所在房间，未生成时为 `None`
+    pub room: Option<String>,
+    /// 剩余寿命
+    pub ticks_to_live: Option<u64>,
+    /// 可以再次生成的时间，unix 时间戳（毫秒），没有冷却或未提供玩家的 token 时为 `None`
+    pub spawn_cooldown_time: Option<i64>,
+    /// 被删除的时间，unix 时间戳（毫秒），未在删除中或未提供玩家的 token 时为 `None`
+    pub delete_time: Option<i64>,
+}
+
+/// 玩家超能 creep 概况
+#[derive(Serialize, ToSchema)]
+pub struct PowerCreepsOverview {
+    pub gpl: u32,
+    /// 已被超能 creep 占用的 GPL 等级，包括未生成的超能 creep，需要提供玩家的 token 才有
+    pub used_levels: Option<u32>,
+    /// 剩余可用于创建或升级超能 creep 的 GPL 等级，需要提供玩家的 token 才有
+    pub free_levels: Option<u32>,
+    /// 玩家的超能 creep
+    /// 没有提供玩家的 token 时只有生成在玩家房间中的超能 creep，不包括未生成的和在过道或其他玩家房间中的超能 creep
+    pub power_creeps: Vec<PowerCreepInfo>,
+}
+
+/// 超能力的等级和剩余冷却
+/// 参数：
+/// - cooldown_time: 冷却结束的游戏 tick
+/// - game_time: 超能 creep 所在 shard 当前的游戏 tick
+fn power_status(id: &str, level: u32, cooldown_time: Option<u64>, game_time: u64) -> PowerStatus {
+    PowerStatus {
+        id: id.to_string(),
+        name: id
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| POWER_NAMES.get(i))
+            .unwrap_or(&"")
+            .to_string(),
+        level,
+        cooldown: cooldown_time
+            .map(|t| t.saturating_sub(game_time))
+            .unwrap_or(0),
+    }
+}
+
+/// 将玩家超能 creep 列表中的一项转换为超能 creep 状态
+/// 参数：
+/// - game_time: 超能 creep 所在 shard 当前的游戏 tick，未生成时为 `None`
+fn power_creep_from_list(creep: &Value, game_time: Option<u64>) -> PowerCreepInfo {
+    let text = |key: &str| creep[key].as_str().map(str::to_string);
+    let game_time = game_time.unwrap_or(0);
+    let mut powers: Vec<_> = creep["powers"]
+        .as_object()
+        .map(|powers| {
+            powers
+                .iter()
+                .map(|(id, power)| {
+                    power_status(
+                        id,
+                        power["level"].as_u64().unwrap_or(0) as u32,
+                        power["cooldownTime"].as_u64(),
+                        game_time,
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    powers.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let now = Utc::now().timestamp_millis();
+    PowerCreepInfo {
+        name: text("name").unwrap_or_default(),
+        class_name: text("className").unwrap_or_default(),
+        level: creep["level"].as_u64().unwrap_or(0) as u32,
+        powers,
+        shard: text("shard"),
+        room: text("room"),
+        ticks_to_live: creep["ageTime"]
+            .as_u64()
+            .map(|t| t.saturating_sub(game_time))
+            .filter(|t| *t > 0),
+        spawn_cooldown_time: creep["spawnCooldownTime"].as_i64().filter(|t| *t > now),
+        delete_time: creep["deleteTime"].as_i64(),
+    }
+}
+
+/// 使用玩家自己的 token 查询所有超能 creep，包括未生成的和在过道或其他玩家房间中的超能 creep
+async fn query_power_creep_list(
+    api: &ScreepsApi,
+    token: &str,
+) -> ScreepsResult<Vec<PowerCreepInfo>> {
+    let response = track_upstream(
+        "power_creeps",
+        request_api(
+            &reqwest::Client::new(),
+            token,
+            "game/power-creeps/list",
+            &[],
+        ),
+    )
+    .await?;
+    let list = response["list"].as_array().cloned().unwrap_or_default();
+
+    // 已生成的超能 creep 需要所在 shard 的游戏 tick 计算寿命和冷却
+    let mut game_times = HashMap::new();
+    for shard in list.iter().filter_map(|creep| creep["shard"].as_str()) {
+        if !game_times.contains_key(shard) {
+            game_times.insert(shard.to_string(), query_game_time(api, shard).await?);
+        }
+    }
+    Ok(list
+        .iter()
+        .map(|creep| {
+            let game_time = creep["shard"]
+                .as_str()
+                .and_then(|shard| game_times.get(shard).copied());
+            power_creep_from_list(creep, game_time)
+        })
+        .collect())
+}
+
+/// 查询玩家的超能 creep
+/// 提供玩家的 token 时从玩家自己的超能 creep 列表查询，并返回占用和剩余的 GPL 等级；
+/// 没有提供 token 时只能从玩家房间的公开数据中查询，不包括未生成的和在过道或其他玩家房间中的超能 creep
+/// 参数：
+/// - username: 玩家名称
+/// - owner_token: 调用者提供的玩家 token，与玩家注册的 token 相同时才能查询
+pub async fn query_power_creeps(
+    api: &ScreepsApi,
+    username: &str,
+    owner_token: Option<&str>,
+) -> ScreepsResult<PowerCreepsOverview> {
+    let user_info = track_upstream("user_find", api.get_user_info_by_name(username)).await?;
+    if user_info.base_data.ok == None || user_info.base_data.ok.unwrap() != 1 {
+        return Err(error(ErrorCode::NotFound, "玩家不存在"));
+    }
+    let user = user_info.user.unwrap();
+    let (gpl, _, _) = gpl_level(user.power as f64);
+
+    if owner_token.is_some() {
+        let token = tokens::owner_token(username, owner_token)?;
+        let mut power_creeps = query_power_creep_list(api, &token).await?;
+        power_creeps.sort_by(|a, b| a.name.cmp(&b.name));
+        // 每个超能 creep 占用的等级为其等级加一
+        let used_levels = power_creeps.iter().map(|creep| creep.level + 1).sum();
+        return Ok(PowerCreepsOverview {
+            gpl,
+            used_levels: Some(used_levels),
+            free_levels: Some(gpl.saturating_sub(used_levels)),
+            power_creeps,
+        });
+    }
+
+    let user_rooms = query_user_rooms(api, &user._id).await?;
+    let room_objects_list = fetch_room_objects(api, &user_rooms, "all").await?;
+    let game_times = query_game_times(api, &room_objects_list).await?;
+
+    let mut power_creeps = Vec::new();
+    for room_objects in room_objects_list {
+        let game_time = game_times[&room_objects.shard];
+        for room_object in room_objects.objects {
+            let RoomObject::PowerCreep(power_creep) = room_object else {
+                continue;
+            };
+            if power_creep.user.as_deref() != Some(user._id.as_str()) {
+                continue;
+            }
+
+            let mut powers: Vec<_> = power_creep
+                .powers
+                .iter()
+                .map(|(id, power)| power_status(id, power.level, power.cooldown_time, game_time))
+                .collect();
+            powers.sort_by(|a, b| a.name.cmp(&b.name));
+
+            power_creeps.push(PowerCreepInfo {
+                name: power_creep.name.clone(),
+                class_name: power_creep.class_name.clone().unwrap_or_default(),
+                level: power_creep.level.unwrap_or(0),
+                powers,
+                shard: Some(room_objects.shard.clone()),
+                room: Some(room_objects.room.clone()),
+                ticks_to_live: power_creep
+                    .age_time
+                    .map(|t| t.saturating_sub(game_time))
+                    .filter(|t| *t > 0),
+                spawn_cooldown_time: None,
+                delete_time: None,
+            });
+        }
+    }
+    power_creeps.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(PowerCreepsOverview {
+        gpl,
+        used_levels: None,
+        free_levels: None,
+        power_creeps,
+    })
+}
+
+/// 绘制等级进度条
+fn draw_progress_bar<T: DrawingBackend>(
+    root: &DrawingArea<T, plotters::coord::Shift>,
+    progress: &LevelProgress,
+    x: i32,
+    y: i32,
+    color: &str,
+) {
+    let ratio = if progress.progress_total > 0.0 {
+        progress.progress / progress.progress_total
+    } else {
+        0.0
+    };
+    draw_bar(root, x, y, 200, ratio, color);
+}
+
+/// 绘制玩家概览卡片
+pub async fn draw_user_overview_image(
+    api: &ScreepsApi,
+    username: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let overview = query_user_overview(api, username, None).await?;
+    let _timer = RenderTimer::new("overview");
+    render_png((480, 200), |root| {
+        root.fill(&parse_color("#2b2b2b").unwrap())?;
+
+        draw_text(root, &overview.username, 20, 15, 24, "#ffffff");
+
+        draw_res_text(
+            root,
+            &format!("GCL {}", overview.gcl.level),
+            20,
+            55,
+            "rgb(76, 167, 229)",
+        );
+        draw_progress_bar(root, &overview.gcl, 90, 58, "rgb(76, 167, 229)");
+
+        draw_res_text(
+            root,
+            &format!("GPL {}", overview.gpl.level),
+            20,
+            80,
+            "rgb(224,90,90)",
+        );
+        draw_progress_bar(root, &overview.gpl, 90, 83, "rgb(224,90,90)");
+
+        // 每个 shard 的房间数量
+        let mut shards: Vec<_> = overview.room_count.iter().collect();
+        shards.sort();
+        for (i, (shard, count)) in shards.iter().enumerate() {
+            draw_res_text(
+                root,
+                &format!("{}: {} rooms", shard, format_number(**count as i32)),
+                320,
+                55 + (i as u32) * 20,
+                "#ffffff",
+            );
+        }
+
+        // 控制器等级分布
+        let mut rcl_count = [0; 9];
+        for rooms in overview.rooms.values() {
+            for level in rooms.values() {
+                rcl_count[(*level as usize).min(8)] += 1;
+            }
+        }
+        draw_res_text(root, "RCL", 20, 115, "#ffffff");
+        for level in 1..=8 {
+            draw_res_text(
+                root,
+                &format!("{}: {}", level, rcl_count[level]),
+                20 + (level as u32 - 1) * 55,
+                135,
+                "rgb(255,242,0)",
+            );
+        }
+
+        // 当前时间
+        let now: DateTime<Local> = Local::now();
+        let time_str = now.format("%Y/%m/%d %H:%M:%S").to_string();
+        draw_res_text(root, &time_str, 320, 175, "#888");
+        Ok(())
+    })
+}
@@ -1,5 +1,10 @@
+use crate::constants::{GCL_MULTIPLY, GCL_POW, POWER_LEVEL_MULTIPLY, POWER_LEVEL_POW};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::{DateTime, Local};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use image::{ColorType, ImageEncoder, codecs::png::PngEncoder};
 use plotters::{coord::Shift, prelude::*};
-use std::{collections::HashMap, fs, path::Path, str::FromStr};
+use std::{collections::HashMap, fs, io::Read, path::Path, str::FromStr};
 
 /// 将 HEX 颜色或 RGB 颜色字符串转换为 RGBColor
 /// 支持以下格式：
@@ -75,6 +80,27 @@ pub fn draw_res_text<T: DrawingBackend>(
     let _ = draw_text(root, res_type, x, y, 14, color);
 }
 
+/// 绘制比例条
+pub fn draw_bar<T: DrawingBackend>(
+    root: &DrawingArea<T, Shift>,
+    x: i32,
+    y: i32,
+    width: u32,
+    ratio: f64,
+    color: &str,
+) {
+    let color = parse_color(color).unwrap_or(RGBColor(255, 255, 255));
+    let filled = (width as f64 * ratio.clamp(0.0, 1.0)) as i32;
+    let _ = root.draw(&Rectangle::new(
+        [(x, y), (x + width as i32, y + 10)],
+        RGBColor(68, 68, 68).filled(),
+    ));
+    let _ = root.draw(&Rectangle::new(
+        [(x, y), (x + filled, y + 10)],
+        color.filled(),
+    ));
+}
+
 /// 绘制资源
 pub fn draw_res<T: DrawingBackend>(
     root: &DrawingArea<T, Shift>,
@@ -94,6 +120,97 @@ pub fn draw_res<T: DrawingBackend>(
     );
 }
 
+/// 绘制折线图，横轴为时间
+/// 参数：
+/// - points: (unix 时间戳（秒）, 数值)，按时间排序
+pub fn draw_line_chart<T: DrawingBackend>(
+    root: &DrawingArea<T, Shift>,
+    title: &str,
+    points: &[(i64, f64)],
+    color: &str,
+) {
+    let color = parse_color(color).unwrap_or(RGBColor(255, 255, 255));
+    let text_color = RGBColor(136, 136, 136);
+    let (start, end) = match (points.first(), points.last()) {
+        (Some((start, _)), Some((end, _))) => (*start, (*end).max(start + 1)),
+        _ => (0, 1),
+    };
+    let max = points.iter().map(|(_, v)| *v).fold(0.0, f64::max);
+
+    let Ok(mut chart) = ChartBuilder::on(root)
+        .caption(title, ("sans-serif", 14).into_font().color(&WHITE))
+        .margin(10)
+        .x_label_area_size(20)
+        .y_label_area_size(60)
+        .build_cartesian_2d(start..end, 0.0..(max * 1.1).max(1.0))
+    else {
+        return;
+    };
+    let _ = chart
+        .configure_mesh()
+        .axis_style(text_color)
+        .bold_line_style(RGBColor(68, 68, 68))
+        .light_line_style(RGBColor(51, 51, 51))
+        .label_style(("sans-serif", 12).into_font().color(&text_color))
+        .x_labels(6)
+        .x_label_formatter(&|time| {
+            DateTime::from_timestamp(*time, 0)
+                .map(|t| t.with_timezone(&Local).format("%m/%d %H:%M").to_string())
+                .unwrap_or_default()
+        })
+        .draw();
+    let _ = chart.draw_series(LineSeries::new(points.iter().copied(), &color));
+}
+
+/// 在内存中绘制图片并编码为 PNG，不写入文件
+/// 参数：
+/// - size: 图片的宽和高
+/// - draw: 在画布上绘制，结束后自动提交
+pub fn render_png(
+    size: (u32, u32),
+    draw: impl FnOnce(&DrawingArea<BitMapBackend<'_>, Shift>) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let (width, height) = size;
+    let mut buffer = vec![0; width as usize * height as usize * 3];
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, size).into_drawing_area();
+        draw(&root)?;
+        root.present()?;
+    }
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).write_image(&buffer, width, height, ColorType::Rgb8)?;
+    Ok(png)
+}
+
+/// 解码 Screeps 的压缩数据，以 `gz:` 开头时为 base64 编码的 gzip 或 zlib 数据，否则原样返回
+pub fn decode_gz(text: &str) -> Result<String, String> {
+    let Some(data) = text.strip_prefix("gz:") else {
+        return Ok(text.to_string());
+    };
+    let bytes = STANDARD.decode(data).map_err(|e| e.to_string())?;
+    let mut result = String::new();
+    // gzip 数据以 0x1f 0x8b 开头
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(bytes.as_slice()).read_to_string(&mut result)
+    } else {
+        ZlibDecoder::new(bytes.as_slice()).read_to_string(&mut result)
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+/// 读取 store 中指定资源的数量
+pub fn store_amount<'a, K: ToString + 'a>(
+    store: impl IntoIterator<Item = (&'a K, &'a Option<i32>)>,
+    resource: &str,
+) -> i32 {
+    store
+        .into_iter()
+        .filter(|(resource_type, _)| resource_type.to_string() == resource)
+        .map(|(_, amount)| amount.unwrap_or(0))
+        .sum()
+}
+
 /// 将所有shard的资源统计合在一起
 pub fn merge_res(res_map: &HashMap<String, HashMap<String, i32>>) -> HashMap<String, i32> {
     let mut res_sum = HashMap::new();
@@ -117,17 +234,17 @@ pub fn create_data_dir() -> Result<(), Box<dyn std::error::Error>> {
 /// 千分位分割数字
 pub fn format_number(num: i32) -> String {
     let num_str = num.to_string();
-    
+
     // 处理负数情况
     let (prefix, digits) = if num_str.starts_with('-') {
         ("-", &num_str[1..])
     } else {
         ("", &num_str[..])
     };
-    
+
     let len = digits.len();
     let mut result = String::from(prefix);
-    
+
     for (i, ch) in digits.chars().enumerate() {
         // 计算当前字符后是否需要添加逗号
         // 从右边数起，每三位数字后添加一个逗号
@@ -136,10 +253,28 @@ pub fn format_number(num: i32) -> String {
         }
         result.push(ch);
     }
-    
+
     result
 }
 
+/// 根据 GCL 点数计算等级，返回 (等级, 当前等级进度, 升级所需进度)
+pub fn gcl_level(points: f64) -> (u32, f64, f64) {
+    let level = (points / GCL_MULTIPLY).powf(1.0 / GCL_POW).floor() as u32 + 1;
+    let base = ((level - 1) as f64).powf(GCL_POW) * GCL_MULTIPLY;
+    let next = (level as f64).powf(GCL_POW) * GCL_MULTIPLY;
+    (level, points - base, next - base)
+}
+
+/// 根据 power 点数计算 GPL 等级，返回 (等级, 当前等级进度, 升级所需进度)
+pub fn gpl_level(points: f64) -> (u32, f64, f64) {
+    let level = (points / POWER_LEVEL_MULTIPLY)
+        .powf(1.0 / POWER_LEVEL_POW)
+        .floor() as u32;
+    let base = (level as f64).powf(POWER_LEVEL_POW) * POWER_LEVEL_MULTIPLY;
+    let next = ((level + 1) as f64).powf(POWER_LEVEL_POW) * POWER_LEVEL_MULTIPLY;
+    (level, points - base, next - base)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -158,4 +293,34 @@ mod tests {
         assert_eq!(format_number(-1234567), "-1,234,567");
         assert_eq!(format_number(-1234), "-1,234");
     }
+
+    #[test]
+    fn test_gcl_level() {
+        assert_eq!(gcl_level(0.0), (1, 0.0, 1_000_000.0));
+        assert_eq!(gcl_level(999_999.0).0, 1);
+        assert_eq!(gcl_level(1_000_000.0).0, 2);
+        let (level, progress, _) = gcl_level(6_000_000.0);
+        assert_eq!(level, 3);
+        assert!(progress > 0.0);
+    }
+
+    #[test]
+    fn test_gpl_level() {
+        assert_eq!(gpl_level(0.0), (0, 0.0, 1000.0));
+        assert_eq!(gpl_level(1000.0), (1, 0.0, 3000.0));
+        assert_eq!(gpl_level(5000.0), (2, 1000.0, 5000.0));
+    }
+
+    #[test]
+    fn test_decode_gz() {
+        use flate2::{Compression, write::GzEncoder};
+        use std::io::Write;
+
+        assert_eq!(decode_gz("{}").unwrap(), "{}");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"{\"cpu\":1}").unwrap();
+        let text = format!("gz:{}", STANDARD.encode(encoder.finish().unwrap()));
+        assert_eq!(decode_gz(&text).unwrap(), "{\"cpu\":1}");
+        assert!(decode_gz("gz:???").is_err());
+    }
 }
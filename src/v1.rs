@@ -0,0 +1,817 @@
+use crate::{
+    BalanceQueryParams, CpuQueryParams, CreepQueryParams, ExportQueryParams, MemoryQueryParams,
+    ResImageQueryParams, ResQueryParams, RoomQueryParams, StructureQueryParams, UserQueryParams,
+    config::Config,
+    cpu, creep, defense,
+    error::{self as query_error, ErrorCode},
+    export_file, file_response, headers,
+    history::{HistoryRecord, HistorySource},
+    ingest, memory,
+    metrics::{RequestStats, with_request_stats},
+    plan, png_response, privacy, report, res, room, stats, tokens, user,
+};
+use axum::{
+    Router,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{HeaderMap, StatusCode, request::Parts},
+    middleware,
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+};
+use chrono::{Local, TimeDelta};
+use screeps_rust_api::{ScreepsApi, ScreepsResult};
+use serde::{Serialize, de::DeserializeOwned};
+use std::{future::Future, sync::Arc, time::Instant};
+use utoipa::{OpenApi, ToSchema};
+
+/// 错误信息
+#[derive(Serialize, ToSchema)]
+pub struct ApiError {
+    /// 错误码：`upstream_error` 查询失败，`render_error` 图片绘制失败，`not_found` 玩家或资源不存在，
+    /// `bad_request` 请求参数错误，`unauthorized` 未提供有效的 API key 或 token，
+    /// `forbidden` 无权查询，`rate_limited` 请求过于频繁，`unavailable` 服务未开启相关功能
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// 响应元信息
+#[derive(Serialize, ToSchema)]
+pub struct Meta {
+    /// 请求总耗时（毫秒）
+    pub duration_ms: u64,
+    /// 上游 Screeps API 请求次数
+    pub upstream_requests: u32,
+    /// 上游请求耗时之和（毫秒），并发请求的耗时会累加
+    pub upstream_ms: u64,
+    /// 数据是否来自缓存
+    pub cached: bool,
+    /// 数据的获取时间，使用缓存时为缓存写入时间
+    pub fetched_at: String,
+}
+
+/// v1 接口统一的响应格式，成功时 `data` 有值，失败时 `error` 有值
+#[derive(Serialize, ToSchema)]
+pub struct Envelope<T> {
+    pub data: Option<T>,
+    pub error: Option<ApiError>,
+    pub meta: Meta,
+}
+
+impl Meta {
+    fn new(start: Instant, stats: RequestStats) -> Self {
+        let age = stats
+            .cache_age
+            .and_then(|age| TimeDelta::from_std(age).ok())
+            .unwrap_or_default();
+        Meta {
+            duration_ms: start.elapsed().as_millis() as u64,
+            upstream_requests: stats.upstream_requests,
+            upstream_ms: stats.upstream_duration.as_millis() as u64,
+            cached: stats.cache_age.is_some(),
+            fetched_at: (Local::now() - age).to_rfc3339(),
+        }
+    }
+}
+
+/// 构建 JSON 响应
+fn envelope_response<T: Serialize>(
+    status: StatusCode,
+    data: Option<T>,
+    error: Option<ApiError>,
+    meta: Meta,
+) -> Response {
+    (status, Json(Envelope { data, error, meta })).into_response()
+}
+
+/// 构建错误响应
+fn error_response(status: StatusCode, code: &'static str, message: String, meta: Meta) -> Response {
+    envelope_response::<()>(status, None, Some(ApiError { code, message }), meta)
+}
+
+/// 查询错误的响应，没有错误码的错误使用 `fallback` 错误码和 500 状态码
+fn query_error_response(
+    (code, message): (Option<ErrorCode>, String),
+    fallback: &'static str,
+    meta: Meta,
+) -> Response {
+    match code {
+        Some(code) => error_response(code.status(), code.code(), message, meta),
+        None => error_response(StatusCode::INTERNAL_SERVER_ERROR, fallback, message, meta),
+    }
+}
+
+/// 执行查询并转换为 JSON 响应
+async fn json<T: Serialize>(request: impl Future<Output = ScreepsResult<T>>) -> Response {
+    let start = Instant::now();
+    let (result, stats) = with_request_stats(request).await;
+    let meta = Meta::new(start, stats);
+    match result {
+        Ok(data) => envelope_response(StatusCode::OK, Some(data), None, meta),
+        Err(e) => query_error_response(query_error::classify(&e), "upstream_error", meta),
+    }
+}
+
+/// 执行图片绘制，成功时返回 PNG 图片，失败时返回 JSON 错误
+async fn image(request: impl Future<Output = Result<Vec<u8>, String>>) -> Response {
+    let start = Instant::now();
+    let (result, stats) = with_request_stats(request).await;
+    match result {
+        Ok(png) => png_response(png),
+        Err(e) => query_error_response(
+            query_error::parse(&e),
+            "render_error",
+            Meta::new(start, stats),
+        ),
+    }
+}
+
+/// v1 接口路由，挂载在 `/api/v1` 下
+pub fn router(api: Arc<ScreepsApi>, config: Arc<Config>, report_log: report::ReportLog) -> Router {
+    Router::new()
+        .route(
+            "/res",
+            get({
+                let api = api.clone();
+                move |query: Query<ResQueryParams>| get_res(api.clone(), query)
+            })
+            .layer(middleware::from_fn_with_state(
+                config.clone(),
+                headers::etag_middleware,
+            )),
+        )
+        .route(
+            "/res/image",
+            get({
+                let api = api.clone();
+                move |query: Query<ResImageQueryParams>| get_res_image(api.clone(), query)
+            })
+            .layer(middleware::from_fn_with_state(
+                config.clone(),
+                headers::etag_middleware,
+            )),
+        )
+        .route(
+            "/res/export",
+            get({
+                let api = api.clone();
+                move |query: Query<ExportQueryParams>| get_res_export(api.clone(), query)
+            }),
+        )
+        .route(
+            "/user/overview",
+            get({
+                let api = api.clone();
+                move |headers: HeaderMap, query: Query<UserQueryParams>| {
+                    get_user_overview(api.clone(), headers, query)
+                }
+            }),
+        )
+        .route(
+            "/user/overview/image",
+            get({
+                let api = api.clone();
+                move |query: Query<UserQueryParams>| get_user_overview_image(api.clone(), query)
+            }),
+        )
+        .route(
+            "/user/powercreeps",
+            get({
+                let api = api.clone();
+                move |headers: HeaderMap, query: Query<UserQueryParams>| {
+                    get_power_creeps(api.clone(), headers, query)
+                }
+            }),
+        )
+        .route("/user/live", get(get_user_live))
+        .route("/user/memory", get(get_user_memory))
+        .route("/user/market/orders", get(get_market_orders))
+        .route("/user/cpu", get(get_cpu))
+        .route("/user/cpu/image", get(get_cpu_image))
+        .route(
+            "/rooms/controllers",
+            get({
+                let api = api.clone();
+                move |query: Query<RoomQueryParams>| get_controllers(api.clone(), query)
+            }),
+        )
+        .route(
+            "/rooms/structures",
+            get({
+                let api = api.clone();
+                move |query: Query<StructureQueryParams>| get_structures(api.clone(), query)
+            }),
+        )
+        .route(
+            "/rooms/defense",
+            get({
+                let api = api.clone();
+                move |query: Query<RoomQueryParams>| get_defense(api.clone(), query)
+            }),
+        )
+        .route(
+            "/rooms/defense/image",
+            get({
+                let api = api.clone();
+                move |query: Query<RoomQueryParams>| get_defense_image(api.clone(), query)
+            }),
+        )
+        .route(
+            "/rooms/creeps",
+            get({
+                let api = api.clone();
+                move |query: Query<CreepQueryParams>| get_creeps(api.clone(), query)
+            }),
+        )
+        .route(
+            "/rooms/minerals",
+            get({
+                let api = api.clone();
+                move |query: Query<RoomQueryParams>| get_minerals(api.clone(), query)
+            }),
+        )
+        .route(
+            "/tokens",
+            post({
+                let api = api.clone();
+                let config = config.clone();
+                move |body: Body<tokens::TokenRegistration>| {
+                    post_tokens(api.clone(), config.clone(), body)
+                }
+            })
+            .delete(delete_tokens),
+        )
+        .route(
+            "/plan/balance",
+            get({
+                let api = api.clone();
+                move |query: Query<BalanceQueryParams>| {
+                    get_balance_plan(api.clone(), config.clone(), query)
+                }
+            }),
+        )
+        .route("/privacy", get(get_privacy).post(post_privacy))
+        .route(
+            "/reports/log",
+            get(move || get_report_log(report_log.clone())),
+        )
+        .fallback(not_found)
+}
+
+/// 构建不涉及查询的错误响应
+pub fn error(status: StatusCode, code: &'static str, message: String) -> Response {
+    error_response(
+        status,
+        code,
+        message,
+        Meta::new(Instant::now(), RequestStats::default()),
+    )
+}
+
+/// 查询参数，解析失败时返回统一格式的 `bad_request` 错误
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned, S: Send + Sync> FromRequestParts<S> for Query<T> {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        axum::extract::Query::from_request_parts(parts, state)
+            .await
+            .map(|axum::extract::Query(value)| Query(value))
+            .map_err(|rejection| {
+                error(
+                    StatusCode::BAD_REQUEST,
+                    "bad_request",
+                    rejection.body_text(),
+                )
+            })
+    }
+}
+
+/// JSON 请求体，解析失败时返回统一格式的 `bad_request` 错误
+pub struct Body<T>(pub T);
+
+impl<T: DeserializeOwned, S: Send + Sync> FromRequest<S> for Body<T> {
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        Json::from_request(request, state)
+            .await
+            .map(|Json(value)| Body(value))
+            .map_err(|rejection| {
+                error(
+                    StatusCode::BAD_REQUEST,
+                    "bad_request",
+                    rejection.body_text(),
+                )
+            })
+    }
+}
+
+/// 未知接口
+async fn not_found() -> Response {
+    error(StatusCode::NOT_FOUND, "not_found", "接口不存在".to_string())
+}
+
+/// 查询玩家资源
+#[utoipa::path(
+    get,
+    path = "/res",
+    params(ResQueryParams),
+    responses(
+        (status = 200, description = "各 shard 的资源数量", body = Envelope<res::ShardRes>),
+        (status = 500, description = "查询失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_res(api: Arc<ScreepsApi>, Query(params): Query<ResQueryParams>) -> Response {
+    json(res::query_res_cached(&api, &params.username, &params.shard)).await
+}
+
+/// 绘制玩家资源图片
+#[utoipa::path(
+    get,
+    path = "/res/image",
+    params(ResImageQueryParams),
+    responses(
+        (status = 200, description = "资源图片，`source=stats` 时为统计数据折线图", content_type = "image/png"),
+        (status = 500, description = "绘制失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_res_image(
+    api: Arc<ScreepsApi>,
+    Query(params): Query<ResImageQueryParams>,
+) -> Response {
+    match params.source {
+        HistorySource::Res => {
+            image(async {
+                res::draw_res_image(&api, &params.username, &params.shard)
+                    .await
+                    .map_err(|e| e.to_string())
+            })
+            .await
+        }
+        HistorySource::Stats => {
+            let fields = stats::parse_fields(params.fields.as_deref());
+            image(async {
+                stats::draw_stats_image(
+                    &params.username,
+                    &params.shard,
+                    &fields,
+                    params.from,
+                    params.to,
+                )
+                .map_err(|e| e.to_string())
+            })
+            .await
+        }
+    }
+}
+
+/// 导出玩家资源
+#[utoipa::path(
+    get,
+    path = "/res/export",
+    params(ExportQueryParams),
+    responses(
+        (status = 200, description = "导出的文件", content(
+            (String = "text/csv"),
+            (String = "application/x-ndjson"),
+            (Vec<u8> = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")
+        )),
+        (status = 500, description = "导出失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_res_export(api: Arc<ScreepsApi>, Query(params): Query<ExportQueryParams>) -> Response {
+    let start = Instant::now();
+    let (result, stats) = with_request_stats(export_file(&api, &params)).await;
+    match result {
+        Ok(file) => file_response(file),
+        Err(e) => query_error_response(
+            query_error::parse(&e.to_string()),
+            "upstream_error",
+            Meta::new(start, stats),
+        ),
+    }
+}
+
+/// 查询玩家概览
+#[utoipa::path(
+    get,
+    path = "/user/overview",
+    params(UserQueryParams),
+    responses(
+        (status = 200, description = "玩家概览", body = Envelope<user::UserOverview>),
+        (status = 500, description = "查询失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_user_overview(
+    api: Arc<ScreepsApi>,
+    headers: HeaderMap,
+    Query(params): Query<UserQueryParams>,
+) -> Response {
+    json(user::query_user_overview(
+        &api,
+        &params.username,
+        tokens::request_token(&headers),
+    ))
+    .await
+}
+
+/// 绘制玩家概览卡片
+#[utoipa::path(
+    get,
+    path = "/user/overview/image",
+    params(UserQueryParams),
+    responses(
+        (status = 200, description = "玩家概览卡片", content_type = "image/png"),
+        (status = 500, description = "绘制失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_user_overview_image(
+    api: Arc<ScreepsApi>,
+    Query(params): Query<UserQueryParams>,
+) -> Response {
+    image(async {
+        user::draw_user_overview_image(&api, &params.username)
+            .await
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// 查询玩家超能 creep 状态
+#[utoipa::path(
+    get,
+    path = "/user/powercreeps",
+    params(UserQueryParams, ("X-Screeps-Token" = String, Header, description = "玩家注册的 Screeps token，提供时返回占用和剩余的 GPL 等级")),
+    responses(
+        (status = 200, description = "超能 creep 状态", body = Envelope<user::PowerCreepsOverview>),
+        (status = 500, description = "查询失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_power_creeps(
+    api: Arc<ScreepsApi>,
+    headers: HeaderMap,
+    Query(params): Query<UserQueryParams>,
+) -> Response {
+    json(user::query_power_creeps(
+        &api,
+        &params.username,
+        tokens::request_token(&headers),
+    ))
+    .await
+}
+
+/// 查询玩家实时 CPU 和控制台消息
+#[utoipa::path(
+    get,
+    path = "/user/live",
+    params(UserQueryParams, ("X-Screeps-Token" = String, Header, description = "玩家注册的 Screeps token")),
+    responses(
+        (status = 200, description = "玩家实时状态", body = Envelope<ingest::UserLive>),
+        (status = 401, description = "未提供玩家 token", body = Envelope<()>),
+        (status = 403, description = "玩家未注册 token 或 token 不一致", body = Envelope<()>),
+        (status = 503, description = "未开启 socket 数据接入", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_user_live(headers: HeaderMap, Query(params): Query<UserQueryParams>) -> Response {
+    json(async { ingest::query_user_live(&params.username, tokens::request_token(&headers)) }).await
+}
+
+/// 使用玩家自己的 token 读取玩家 Memory
+#[utoipa::path(
+    get,
+    path = "/user/memory",
+    params(MemoryQueryParams, ("X-Screeps-Token" = String, Header, description = "玩家注册的 Screeps token")),
+    responses(
+        (status = 200, description = "Memory 中指定路径的数据", body = Envelope<serde_json::Value>),
+        (status = 401, description = "未提供玩家 token", body = Envelope<()>),
+        (status = 403, description = "玩家未注册 token 或 token 不一致", body = Envelope<()>),
+        (status = 500, description = "查询失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_user_memory(headers: HeaderMap, Query(params): Query<MemoryQueryParams>) -> Response {
+    json(memory::query_player_memory(
+        &params.username,
+        &params.path,
+        &params.shard,
+        tokens::request_token(&headers),
+    ))
+    .await
+}
+
+/// 使用玩家自己的 token 查询玩家的市场订单
+#[utoipa::path(
+    get,
+    path = "/user/market/orders",
+    params(UserQueryParams, ("X-Screeps-Token" = String, Header, description = "玩家注册的 Screeps token")),
+    responses(
+        (status = 200, description = "玩家的市场订单", body = Envelope<Vec<serde_json::Value>>),
+        (status = 401, description = "未提供玩家 token", body = Envelope<()>),
+        (status = 403, description = "玩家未注册 token 或 token 不一致", body = Envelope<()>),
+        (status = 500, description = "查询失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_market_orders(headers: HeaderMap, Query(params): Query<UserQueryParams>) -> Response {
+    json(user::query_market_orders(
+        &params.username,
+        tokens::request_token(&headers),
+    ))
+    .await
+}
+
+/// 查询玩家 CPU 和内存历史
+#[utoipa::path(
+    get,
+    path = "/user/cpu",
+    params(CpuQueryParams),
+    responses(
+        (status = 200, description = "CPU 和内存历史", body = Envelope<Vec<HistoryRecord<cpu::CpuRecord>>>),
+        (status = 500, description = "查询失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_cpu(Query(params): Query<CpuQueryParams>) -> Response {
+    json(async { cpu::query_cpu(&params.username, &params.shard, params.from, params.to) }).await
+}
+
+/// 绘制玩家 CPU 和内存折线图
+#[utoipa::path(
+    get,
+    path = "/user/cpu/image",
+    params(CpuQueryParams),
+    responses(
+        (status = 200, description = "CPU 和内存折线图", content_type = "image/png"),
+        (status = 500, description = "绘制失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_cpu_image(Query(params): Query<CpuQueryParams>) -> Response {
+    image(async {
+        cpu::draw_cpu_image(&params.username, &params.shard, params.from, params.to)
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// 查询房间控制器信息
+#[utoipa::path(
+    get,
+    path = "/rooms/controllers",
+    params(RoomQueryParams),
+    responses(
+        (status = 200, description = "房间控制器信息", body = Envelope<Vec<room::ControllerInfo>>),
+        (status = 500, description = "查询失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_controllers(api: Arc<ScreepsApi>, Query(params): Query<RoomQueryParams>) -> Response {
+    json(room::query_controllers(
+        &api,
+        &params.username,
+        &params.shard,
+    ))
+    .await
+}
+
+/// 查询房间建筑统计
+#[utoipa::path(
+    get,
+    path = "/rooms/structures",
+    params(StructureQueryParams),
+    responses(
+        (status = 200, description = "房间建筑统计", body = Envelope<Vec<room::RoomStructures>>),
+        (status = 500, description = "查询失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_structures(
+    api: Arc<ScreepsApi>,
+    Query(params): Query<StructureQueryParams>,
+) -> Response {
+    json(room::query_structures(
+        &api,
+        &params.username,
+        &params.shard,
+        params.min_hits,
+    ))
+    .await
+}
+
+/// 查询房间防御状态
+#[utoipa::path(
+    get,
+    path = "/rooms/defense",
+    params(RoomQueryParams),
+    responses(
+        (status = 200, description = "房间防御状态", body = Envelope<Vec<defense::RoomDefense>>),
+        (status = 500, description = "查询失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_defense(api: Arc<ScreepsApi>, Query(params): Query<RoomQueryParams>) -> Response {
+    json(defense::query_defense(
+        &api,
+        &params.username,
+        &params.shard,
+    ))
+    .await
+}
+
+/// 绘制房间防御状态图片
+#[utoipa::path(
+    get,
+    path = "/rooms/defense/image",
+    params(RoomQueryParams),
+    responses(
+        (status = 200, description = "房间防御状态图片", content_type = "image/png"),
+        (status = 500, description = "绘制失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_defense_image(
+    api: Arc<ScreepsApi>,
+    Query(params): Query<RoomQueryParams>,
+) -> Response {
+    image(async {
+        defense::draw_defense_image(&api, &params.username, &params.shard)
+            .await
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// 查询房间 creep 统计
+#[utoipa::path(
+    get,
+    path = "/rooms/creeps",
+    params(CreepQueryParams),
+    responses(
+        (status = 200, description = "房间 creep 统计", body = Envelope<Vec<creep::RoomCreeps>>),
+        (status = 500, description = "查询失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_creeps(api: Arc<ScreepsApi>, Query(params): Query<CreepQueryParams>) -> Response {
+    json(creep::query_creeps(
+        &api,
+        &params.username,
+        &params.shard,
+        params.group_by_prefix,
+    ))
+    .await
+}
+
+/// 查询房间矿物信息
+#[utoipa::path(
+    get,
+    path = "/rooms/minerals",
+    params(RoomQueryParams),
+    responses(
+        (status = 200, description = "房间矿物信息", body = Envelope<room::MineralsOverview>),
+        (status = 500, description = "查询失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_minerals(api: Arc<ScreepsApi>, Query(params): Query<RoomQueryParams>) -> Response {
+    json(room::query_minerals(&api, &params.username, &params.shard)).await
+}
+
+/// 查询资源平衡计划
+#[utoipa::path(
+    get,
+    path = "/plan/balance",
+    params(BalanceQueryParams),
+    responses(
+        (status = 200, description = "资源平衡计划", body = Envelope<plan::BalancePlan>),
+        (status = 500, description = "查询失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn get_balance_plan(
+    api: Arc<ScreepsApi>,
+    config: Arc<Config>,
+    Query(params): Query<BalanceQueryParams>,
+) -> Response {
+    json(plan::query_balance_plan(
+        &api,
+        &params.username,
+        &params.shard,
+        &config.balance,
+    ))
+    .await
+}
+
+/// 查询玩家的数据公开设置
+#[utoipa::path(
+    get,
+    path = "/privacy",
+    params(UserQueryParams),
+    responses(
+        (status = 200, description = "玩家的数据公开设置", body = Envelope<privacy::PlayerPrivacy>)
+    ),
+    tag = "v1"
+)]
+async fn get_privacy(Query(params): Query<UserQueryParams>) -> Response {
+    json(async { Ok(privacy::query_privacy(&params.username)) }).await
+}
+
+/// 玩家使用自己的 Screeps token 注册并设置数据公开范围
+#[utoipa::path(
+    post,
+    path = "/privacy",
+    request_body = privacy::PrivacyRegistration,
+    responses(
+        (status = 200, description = "注册成功，返回玩家的数据公开设置", body = Envelope<privacy::PlayerPrivacy>),
+        (status = 500, description = "token 无效或保存失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn post_privacy(Body(registration): Body<privacy::PrivacyRegistration>) -> Response {
+    json(privacy::register(registration)).await
+}
+
+/// 玩家注册自己的 Screeps token，token 加密保存
+#[utoipa::path(
+    post,
+    path = "/tokens",
+    request_body = tokens::TokenRegistration,
+    responses(
+        (status = 200, description = "注册成功", body = Envelope<tokens::TokenInfo>),
+        (status = 500, description = "token 无效或保存失败", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn post_tokens(
+    api: Arc<ScreepsApi>,
+    config: Arc<Config>,
+    Body(registration): Body<tokens::TokenRegistration>,
+) -> Response {
+    json(tokens::register(api, &config, registration.token)).await
+}
+
+/// 玩家删除自己注册的 token
+#[utoipa::path(
+    delete,
+    path = "/tokens",
+    request_body = tokens::TokenRegistration,
+    responses(
+        (status = 200, description = "删除成功", body = Envelope<tokens::TokenInfo>),
+        (status = 500, description = "token 无效或未注册", body = Envelope<()>)
+    ),
+    tag = "v1"
+)]
+async fn delete_tokens(Body(registration): Body<tokens::TokenRegistration>) -> Response {
+    json(tokens::unregister(registration.token)).await
+}
+
+/// 查询定时报告发送记录
+#[utoipa::path(
+    get,
+    path = "/reports/log",
+    responses(
+        (status = 200, description = "最近的报告发送记录", body = Envelope<Vec<report::ReportLogEntry>>)
+    ),
+    tag = "v1"
+)]
+async fn get_report_log(log: report::ReportLog) -> Response {
+    let entries: Vec<_> = log.lock().unwrap().iter().cloned().collect();
+    json(async { Ok(entries) }).await
+}
+
+/// v1 接口文档，挂载在 `/api/v1` 下
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_res,
+    get_res_image,
+    get_res_export,
+    get_user_overview,
+    get_user_overview_image,
+    get_power_creeps,
+    get_user_live,
+    get_user_memory,
+    get_market_orders,
+    get_cpu,
+    get_cpu_image,
+    get_controllers,
+    get_structures,
+    get_defense,
+    get_defense_image,
+    get_creeps,
+    get_minerals,
+    get_balance_plan,
+    get_privacy,
+    post_privacy,
+    post_tokens,
+    delete_tokens,
+    get_report_log,
+))]
+pub struct V1Doc;
@@ -0,0 +1,62 @@
+use crate::config::{WebhookConfig, WebhookKind};
+use reqwest::multipart::{Form, Part};
+use serde_json::json;
+
+/// 根据 webhook 类型生成消息体
+/// 参数：
+/// - message: 文本消息
+/// - data: 通用 webhook 附带的结构化数据
+pub fn webhook_payload(
+    kind: WebhookKind,
+    message: &str,
+    data: serde_json::Value,
+) -> serde_json::Value {
+    match kind {
+        WebhookKind::Generic => json!({ "message": message, "data": data }),
+        WebhookKind::Discord => json!({ "content": message }),
+        WebhookKind::Slack => json!({ "text": message }),
+    }
+}
+
+/// 发送 webhook 消息
+pub async fn send_webhook(
+    client: &reqwest::Client,
+    webhook: &WebhookConfig,
+    message: &str,
+    data: serde_json::Value,
+) -> Result<(), reqwest::Error> {
+    client
+        .post(&webhook.url)
+        .json(&webhook_payload(webhook.kind, message, data))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// 发送带图片附件的 webhook 消息，图片以 multipart 的 `file` 字段上传
+pub async fn send_webhook_file(
+    client: &reqwest::Client,
+    webhook: &WebhookConfig,
+    message: &str,
+    file_name: &str,
+    content: Vec<u8>,
+) -> Result<(), reqwest::Error> {
+    let file = Part::bytes(content)
+        .file_name(file_name.to_string())
+        .mime_str("image/png")?;
+    let form = match webhook.kind {
+        WebhookKind::Generic => Form::new().text("message", message.to_string()),
+        WebhookKind::Discord => {
+            Form::new().text("payload_json", json!({ "content": message }).to_string())
+        }
+        WebhookKind::Slack => Form::new().text("text", message.to_string()),
+    };
+    client
+        .post(&webhook.url)
+        .multipart(form.part("file", file))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
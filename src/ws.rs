@@ -0,0 +1,201 @@
+use crate::{auth::ApiKey, config::Config, live, privacy, ratelimit};
+use axum::extract::ws::{Message, WebSocket};
+use screeps_rust_api::ScreepsApi;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+
+/// 客户端消息
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ClientMessage {
+    /// 订阅玩家资源，shard 为 `all` 时订阅所有 shard
+    Subscribe {
+        username: String,
+        #[serde(default = "crate::default_shard")]
+        shard: String,
+    },
+    /// 取消订阅
+    Unsubscribe {
+        username: String,
+        #[serde(default = "crate::default_shard")]
+        shard: String,
+    },
+}
+
+/// 服务端消息
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ServerMessage<'a> {
+    /// 完整的资源快照，订阅后或首次收到该 shard 的数据时发送
+    Snapshot {
+        username: &'a str,
+        shard: &'a str,
+        res: &'a HashMap<String, i32>,
+    },
+    /// 资源变化，只包含数量发生变化的资源，消失的资源数量为 0
+    Diff {
+        username: &'a str,
+        shard: &'a str,
+        changes: HashMap<String, i32>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// 发送消息，返回是否发送成功
+async fn send(socket: &mut WebSocket, message: &ServerMessage<'_>) -> bool {
+    let text = serde_json::to_string(message).unwrap();
+    socket.send(Message::Text(text.into())).await.is_ok()
+}
+
+/// 处理一个 WebSocket 连接
+/// 客户端发送 `{"type": "subscribe", "username": "...", "shard": "..."}` 订阅，
+/// 之后每当 `query_res` 查询到该主题的新数据时推送快照或变化，订阅的主题每隔 `LIVE_POLL_INTERVAL` 秒查询一次，
+/// 查询失败时推送错误
+/// 开启认证时只能订阅 API key 允许的玩家和 shard，不能订阅不公开实时数据的玩家
+/// 开启限流时查询的消耗计入建立连接的客户端
+pub async fn handle_socket(
+    api: Arc<ScreepsApi>,
+    config: Arc<Config>,
+    key: Option<ApiKey>,
+    client: Option<ratelimit::Client>,
+    mut socket: WebSocket,
+) {
+    let mut updates = live::subscribe();
+    // 订阅的主题 (玩家名称, shard)
+    let mut topics: HashSet<(String, String)> = HashSet::new();
+    // 已发送给客户端的资源，键为 (玩家名称, shard)
+    let mut sent: HashMap<(String, String), HashMap<String, i32>> = HashMap::new();
+    let (errors_tx, mut errors) = mpsc::unbounded_channel();
+    let mut poll = live::poll_timer();
+
+    loop {
+        tokio::select! {
+            message = socket.recv() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                match serde_json::from_str::<ClientMessage>(text.as_str()) {
+                    Ok(ClientMessage::Subscribe { username, shard }) => {
+                        let denied = if key.as_ref().is_some_and(|key| !key.allows(&username, &shard)) {
+                            Some("API key 无权订阅该玩家或 shard")
+                        } else {
+                            privacy::check(&config.privacy, &username, "/ws").err()
+                        };
+                        if let Some(message) = denied {
+                            let message = ServerMessage::Error {
+                                message: message.to_string(),
+                            };
+                            if !send(&mut socket, &message).await {
+                                break;
+                            }
+                            continue;
+                        }
+                        let snapshots = live::snapshots(&username, &shard);
+                        if snapshots.is_empty() {
+                            // 还没有数据时查询一次
+                            live::request_refresh(
+                                api.clone(),
+                                username.clone(),
+                                shard.clone(),
+                                client.clone(),
+                                errors_tx.clone(),
+                            );
+                        }
+                        for (shard, res) in snapshots {
+                            let message = ServerMessage::Snapshot {
+                                username: &username,
+                                shard: &shard,
+                                res: &res,
+                            };
+                            if !send(&mut socket, &message).await {
+                                return;
+                            }
+                            sent.insert((username.clone(), shard), res);
+                        }
+                        topics.insert((username, shard));
+                    }
+                    Ok(ClientMessage::Unsubscribe { username, shard }) => {
+                        let topic = (username, shard);
+                        topics.remove(&topic);
+                        sent.retain(|(u, s), _| {
+                            topics.iter().any(|(tu, ts)| tu == u && (ts == "all" || ts == s))
+                        });
+                    }
+                    Err(e) => {
+                        let message = ServerMessage::Error {
+                            message: format!("消息格式错误: {}", e),
+                        };
+                        if !send(&mut socket, &message).await {
+                            break;
+                        }
+                    }
+                }
+            }
+            _ = live::next_poll(&mut poll) => {
+                for (username, shard) in &topics {
+                    live::request_refresh(
+                        api.clone(),
+                        username.clone(),
+                        shard.clone(),
+                        client.clone(),
+                        errors_tx.clone(),
+                    );
+                }
+            }
+            Some(error) = errors.recv() => {
+                // 查询期间已取消订阅的主题不再推送错误
+                if !topics.contains(&(error.username.clone(), error.shard.clone())) {
+                    continue;
+                }
+                let message = ServerMessage::Error {
+                    message: format!("查询 {} {} 失败: {}", error.username, error.shard, error.message),
+                };
+                if !send(&mut socket, &message).await {
+                    break;
+                }
+            }
+            update = updates.recv() => {
+                let update = match update {
+                    Ok(update) => update,
+                    // 消费太慢丢失了部分更新，后续更新会带上最新的数据
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                if !topics.iter().any(|topic| live::topic_matches(topic, &update)) {
+                    continue;
+                }
+                let key = (update.username.clone(), update.shard.clone());
+                let message = match sent.get(&key) {
+                    Some(old) => {
+                        let changes = live::diff(old, &update.res, 0);
+                        if changes.is_empty() {
+                            continue;
+                        }
+                        ServerMessage::Diff {
+                            username: &update.username,
+                            shard: &update.shard,
+                            changes,
+                        }
+                    }
+                    None => ServerMessage::Snapshot {
+                        username: &update.username,
+                        shard: &update.shard,
+                        res: &update.res,
+                    },
+                };
+                if !send(&mut socket, &message).await {
+                    break;
+                }
+                sent.insert(key, update.res.clone());
+            }
+        }
+    }
+}